@@ -0,0 +1,200 @@
+//! Serialize byte containers as messagepack bin.
+//!
+//! Serde writes `Vec<u8>` and `[u8; N]` as arrays of integers, which can take up to twice the
+//! space. Use this module with `#[serde(with = "corepack::bytes")]` on a field to write it as bin
+//! instead. Deserializing accepts either form, for any type that can be built from a `Vec<u8>`.
+//! For `[u8; N]`, the `array` submodule does the same and reports the expected length on errors.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{Error, Expected, SeqAccess, Visitor};
+use serde::{Serializer, Deserializer};
+
+struct BytesVisitor<T> {
+    // the length a fixed size container wants, when known
+    len: Option<usize>,
+    phantom: PhantomData<T>,
+}
+
+// what a container that refused the data wanted
+struct ByteCount(Option<usize>);
+
+/// Serialize value as bin.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where T: ?Sized + AsRef<[u8]>,
+          S: Serializer
+{
+    serializer.serialize_bytes(value.as_ref())
+}
+
+/// Deserialize a T from bin, or from an array of bytes.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where T: TryFrom<Vec<u8>>,
+          D: Deserializer<'de>
+{
+    deserializer.deserialize_byte_buf(BytesVisitor {
+        len: None,
+        phantom: PhantomData,
+    })
+}
+
+/// Serialize and deserialize `[u8; N]` as bin.
+pub mod array {
+    use std::marker::PhantomData;
+
+    use serde::{Serializer, Deserializer};
+
+    use super::BytesVisitor;
+
+    /// Serialize value as bin.
+    pub fn serialize<S, const N: usize>(value: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        super::serialize(value, serializer)
+    }
+
+    /// Deserialize exactly N bytes from bin, or from an array of bytes.
+    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_byte_buf(BytesVisitor {
+            len: Some(N),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: TryFrom<Vec<u8>>> BytesVisitor<T> {
+    fn convert<E: Error>(&self, bytes: Vec<u8>) -> Result<T, E> {
+        let len = bytes.len();
+
+        // fixed size containers refuse the wrong number of bytes
+        T::try_from(bytes).map_err(|_| E::invalid_length(len, &ByteCount(self.len)))
+    }
+}
+
+impl Expected for ByteCount {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(len) => write!(fmt, "{} bytes", len),
+            None => fmt.write_str("a byte buffer of the right length"),
+        }
+    }
+}
+
+impl<'de, T: TryFrom<Vec<u8>>> Visitor<'de> for BytesVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("bytes")
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<T, E> {
+        self.convert(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<T, E> {
+        self.convert(v)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<T, A::Error>
+        where A: SeqAccess<'de>
+    {
+        // don't trust the size hint with a large allocation
+        let mut bytes = Vec::with_capacity(cmp::min(seq.size_hint().unwrap_or(0), 4096));
+
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+
+        self.convert(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Packet {
+        #[serde(with = "::bytes")]
+        payload: Vec<u8>,
+        #[serde(with = "::bytes::array")]
+        checksum: [u8; 4],
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Boxed {
+        #[serde(deserialize_with = "::bytes::deserialize")]
+        checksum: Box<[u8; 4]>,
+    }
+
+    #[derive(Serialize)]
+    struct PlainPacket {
+        payload: Vec<u8>,
+        checksum: [u8; 4],
+    }
+
+    #[test]
+    fn bin_test() {
+        let packet = Packet {
+            payload: vec![1, 2, 3],
+            checksum: [0xde, 0xad, 0xbe, 0xef],
+        };
+
+        let bytes = ::to_bytes(&packet).unwrap();
+        assert_eq!(bytes,
+                   &[0x82,
+                     0xa7, 0x70, 0x61, 0x79, 0x6c, 0x6f, 0x61, 0x64,
+                     0xc4, 0x03, 0x01, 0x02, 0x03,
+                     0xa8, 0x63, 0x68, 0x65, 0x63, 0x6b, 0x73, 0x75, 0x6d,
+                     0xc4, 0x04, 0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(::from_bytes::<Packet>(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn array_form_test() {
+        let bytes = ::to_bytes(PlainPacket {
+                payload: vec![1, 2, 3],
+                checksum: [0xde, 0xad, 0xbe, 0xef],
+            })
+            .unwrap();
+
+        let packet: Packet = ::from_bytes(&bytes).unwrap();
+        assert_eq!(packet.payload, vec![1, 2, 3]);
+        assert_eq!(packet.checksum, [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn wrong_length_test() {
+        // a three byte checksum
+        let bytes = [0x82,
+                     0xa7, 0x70, 0x61, 0x79, 0x6c, 0x6f, 0x61, 0x64,
+                     0xc4, 0x00,
+                     0xa8, 0x63, 0x68, 0x65, 0x63, 0x6b, 0x73, 0x75, 0x6d,
+                     0xc4, 0x03, 0xde, 0xad, 0xbe];
+
+        let error = ::from_bytes::<Packet>(&bytes).unwrap_err();
+        assert_eq!(error.to_string(), "invalid length 3, expected 4 bytes");
+
+        // other containers can't say how many bytes they wanted
+        let mut bytes = vec![0x81,
+                             0xa8, 0x63, 0x68, 0x65, 0x63, 0x6b, 0x73, 0x75, 0x6d,
+                             0xc4, 0x04, 0xde, 0xad, 0xbe, 0xef];
+        assert_eq!(*::from_bytes::<Boxed>(&bytes).unwrap().checksum, [0xde, 0xad, 0xbe, 0xef]);
+
+        bytes[11] = 0x03;
+        bytes.pop();
+
+        let error = ::from_bytes::<Boxed>(&bytes).unwrap_err();
+        assert_eq!(error.to_string(),
+                   "invalid length 3, expected a byte buffer of the right length");
+    }
+}
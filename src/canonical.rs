@@ -0,0 +1,359 @@
+//! Checking whether encoded data is in canonical form.
+//!
+//! Canonical data is what the Serializer produces with `SerializerConfig::canonical` set: every
+//! integer, length and header uses its smallest marker, non-negative integers use the unsigned
+//! markers, NaNs use a single bit pattern, and map keys are unique and sorted by their encoded
+//! bytes. Since the length is part of a key's encoding, shorter string keys sort first.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use byteorder::{ByteOrder, BigEndian};
+
+use defs::*;
+use error::Error;
+
+// an array or map whose items are still being checked
+struct Frame {
+    // items left, counting map keys and values separately
+    remaining: usize,
+    map: bool,
+    // where the item being checked starts
+    item_start: usize,
+    // where the previous key of a map lies
+    previous: Option<(usize, usize)>,
+}
+
+/// Check that bytes hold exactly one value, in canonical form.
+///
+/// Returns an error if the data is malformed, and false if it is well-formed but not canonical.
+/// Nested containers are tracked on the heap, so deeply nested input can't overflow the stack.
+pub fn is_canonical(bytes: &[u8]) -> Result<bool, Error> {
+    let mut position = 0;
+    let mut stack: Vec<Frame> = vec![];
+
+    loop {
+        if let Some(frame) = stack.last_mut() {
+            frame.item_start = position;
+        }
+
+        let (minimal, container) = check_value(bytes, &mut position)?;
+        if !minimal {
+            return Ok(false);
+        }
+
+        if let Some(frame) = container {
+            if frame.remaining > 0 {
+                stack.push(frame);
+                continue;
+            }
+        }
+
+        // a value is complete, and maybe the containers it finishes
+        while let Some(frame) = stack.last_mut() {
+            if frame.map && frame.remaining % 2 == 0 {
+                // keys have to be strictly increasing, which also rules out duplicates
+                if let Some((start, end)) = frame.previous {
+                    if bytes[start..end] >= bytes[frame.item_start..position] {
+                        return Ok(false);
+                    }
+                }
+                frame.previous = Some((frame.item_start, position));
+            }
+
+            frame.remaining -= 1;
+            if frame.remaining > 0 {
+                break;
+            }
+
+            stack.pop();
+        }
+
+        if stack.is_empty() {
+            return Ok(position == bytes.len());
+        }
+    }
+}
+
+fn take<'a>(bytes: &'a [u8], position: &mut usize, len: usize) -> Result<&'a [u8], Error> {
+    if len > bytes.len() - *position {
+        return Err(Error::EndOfStream);
+    }
+
+    let result = &bytes[*position..*position + len];
+    *position += len;
+    Ok(result)
+}
+
+fn take_length(bytes: &[u8], position: &mut usize, size: usize) -> Result<usize, Error> {
+    let buf = take(bytes, position, size)?;
+
+    Ok(match size {
+        1 => buf[0] as usize,
+        U16_BYTES => BigEndian::read_u16(buf) as usize,
+        _ => BigEndian::read_u32(buf) as usize,
+    })
+}
+
+// checks a str, bin or ext payload, given whether its marker was the smallest one
+fn check_payload(bytes: &[u8],
+                 position: &mut usize,
+                 len: usize,
+                 minimal: bool)
+                 -> Result<bool, Error> {
+    take(bytes, position, len)?;
+    Ok(minimal)
+}
+
+fn container(map: bool, count: usize) -> Result<Option<Frame>, Error> {
+    let remaining = if map {
+        count.checked_mul(2).ok_or(Error::TooBig)?
+    } else {
+        count
+    };
+
+    Ok(Some(Frame {
+        remaining,
+        map,
+        item_start: 0,
+        previous: None,
+    }))
+}
+
+// checks the value at position, returning whether its header is canonical, and a frame for its
+// items if it is a container
+fn check_value(bytes: &[u8], position: &mut usize) -> Result<(bool, Option<Frame>), Error> {
+    let ty = take(bytes, position, 1)?[0];
+
+    let minimal = match ty {
+        v if POS_FIXINT.contains(v) || NEG_FIXINT.contains(v) => Ok(true),
+        v if FIXMAP.contains(v) => {
+            return Ok((true, container(true, (v & !FIXMAP_MASK) as usize)?));
+        }
+        v if FIXARRAY.contains(v) => {
+            return Ok((true, container(false, (v & !FIXARRAY_MASK) as usize)?));
+        }
+        v if FIXSTR.contains(v) => {
+            check_payload(bytes, position, (v & !FIXSTR_MASK) as usize, true)
+        }
+        NIL | FALSE | TRUE => Ok(true),
+        BIN8 => {
+            let len = take_length(bytes, position, 1)?;
+            check_payload(bytes, position, len, true)
+        }
+        BIN16 => {
+            let len = take_length(bytes, position, U16_BYTES)?;
+            check_payload(bytes, position, len, len > MAX_BIN8)
+        }
+        BIN32 => {
+            let len = take_length(bytes, position, U32_BYTES)?;
+            check_payload(bytes, position, len, len > MAX_BIN16)
+        }
+        EXT8 => {
+            let len = take_length(bytes, position, 1)?;
+            let fixed = len == 1 || len == 2 || len == 4 || len == 8 || len == 16;
+            check_payload(bytes, position, len + 1, !fixed)
+        }
+        EXT16 => {
+            let len = take_length(bytes, position, U16_BYTES)?;
+            check_payload(bytes, position, len + 1, len > MAX_BIN8)
+        }
+        EXT32 => {
+            let len = take_length(bytes, position, U32_BYTES)?;
+            check_payload(bytes, position, len.saturating_add(1), len > MAX_BIN16)
+        }
+        FLOAT32 => {
+            let bits = BigEndian::read_u32(take(bytes, position, U32_BYTES)?);
+            let nan = (bits & 0x7f80_0000) == 0x7f80_0000 && (bits & 0x007f_ffff) != 0;
+            Ok(!nan || bits == CANONICAL_NAN32)
+        }
+        FLOAT64 => {
+            let bits = BigEndian::read_u64(take(bytes, position, U64_BYTES)?);
+            let nan = (bits & 0x7ff0_0000_0000_0000) == 0x7ff0_0000_0000_0000 &&
+                      (bits & 0x000f_ffff_ffff_ffff) != 0;
+            Ok(!nan || bits == CANONICAL_NAN64)
+        }
+        UINT8 => Ok(take(bytes, position, 1)?[0] > FIXINT_MAX),
+        UINT16 => Ok(BigEndian::read_u16(take(bytes, position, U16_BYTES)?) > u8::MAX as u16),
+        UINT32 => Ok(BigEndian::read_u32(take(bytes, position, U32_BYTES)?) > u16::MAX as u32),
+        UINT64 => Ok(BigEndian::read_u64(take(bytes, position, U64_BYTES)?) > u32::MAX as u64),
+        // signed markers are only for negative values that don't fit the next smaller marker
+        INT8 => Ok(read_signed(take(bytes, position, 1)?[0]) < FIXINT_MIN),
+        INT16 => Ok(BigEndian::read_i16(take(bytes, position, U16_BYTES)?) < i8::MIN as i16),
+        INT32 => Ok(BigEndian::read_i32(take(bytes, position, U32_BYTES)?) < i16::MIN as i32),
+        INT64 => Ok(BigEndian::read_i64(take(bytes, position, U64_BYTES)?) < i32::MIN as i64),
+        FIXEXT1 => check_payload(bytes, position, 2, true),
+        FIXEXT2 => check_payload(bytes, position, 3, true),
+        FIXEXT4 => check_payload(bytes, position, 5, true),
+        FIXEXT8 => check_payload(bytes, position, 9, true),
+        FIXEXT16 => check_payload(bytes, position, 17, true),
+        STR8 => {
+            let len = take_length(bytes, position, 1)?;
+            check_payload(bytes, position, len, len > MAX_FIXSTR)
+        }
+        STR16 => {
+            let len = take_length(bytes, position, U16_BYTES)?;
+            check_payload(bytes, position, len, len > MAX_STR8)
+        }
+        STR32 => {
+            let len = take_length(bytes, position, U32_BYTES)?;
+            check_payload(bytes, position, len, len > MAX_STR16)
+        }
+        ARRAY16 | ARRAY32 => {
+            let size = if ty == ARRAY16 { U16_BYTES } else { U32_BYTES };
+            let minimum = if ty == ARRAY16 { MAX_FIXARRAY } else { MAX_ARRAY16 };
+            let len = take_length(bytes, position, size)?;
+
+            return Ok((len > minimum, container(false, len)?));
+        }
+        MAP16 | MAP32 => {
+            let size = if ty == MAP16 { U16_BYTES } else { U32_BYTES };
+            let minimum = if ty == MAP16 { MAX_FIXMAP } else { MAX_MAP16 };
+            let len = take_length(bytes, position, size)?;
+
+            return Ok((len > minimum, container(true, len)?));
+        }
+        _ => Err(Error::BadType),
+    };
+
+    Ok((minimal?, None))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::f64;
+
+    use error::Error;
+    use SerializerConfig;
+
+    use super::is_canonical;
+
+    fn canonical<V: ::serde::Serialize>(value: V) -> Vec<u8> {
+        ::to_bytes_with_config(value, SerializerConfig::new().canonical(true)).unwrap()
+    }
+
+    #[derive(Serialize)]
+    struct Record {
+        zeta: i64,
+        alpha: f64,
+        middle: HashMap<String, u8>,
+    }
+
+    #[test]
+    fn sorted_map_test() {
+        let mut first = HashMap::new();
+        let mut second = HashMap::new();
+
+        for i in 0..40 {
+            first.insert(format!("key{}", i), i);
+            second.insert(format!("key{}", 39 - i), 39 - i);
+        }
+
+        let first = canonical(&first);
+        assert_eq!(first, canonical(&second));
+        assert!(is_canonical(&first).unwrap());
+    }
+
+    #[test]
+    fn struct_test() {
+        let mut middle = HashMap::new();
+        middle.insert("b".into(), 2);
+        middle.insert("a".into(), 1);
+
+        let bytes = canonical(Record {
+            zeta: 300,
+            alpha: f64::NAN,
+            middle,
+        });
+
+        assert_eq!(bytes,
+                   &[0x83, // map with three entries, sorted by encoded key
+                     0xa4, 0x7a, 0x65, 0x74, 0x61, // "zeta": 300 as uint16
+                     0xcd, 0x01, 0x2c,
+                     0xa5, 0x61, 0x6c, 0x70, 0x68, 0x61, // "alpha": canonical NaN
+                     0xcb, 0x7f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0xa6, 0x6d, 0x69, 0x64, 0x64, 0x6c, 0x65, // "middle": {"a": 1, "b": 2}
+                     0x82, 0xa1, 0x61, 0x01, 0xa1, 0x62, 0x02]);
+        assert!(is_canonical(&bytes).unwrap());
+    }
+
+    #[test]
+    fn duplicate_key_test() {
+        let result = ::to_bytes_with_config(vec![(1, 2), (1, 3)].into_iter().collect::<Map>(),
+                                            SerializerConfig::new().canonical(true));
+
+        match result {
+            Err(Error::DuplicateKey) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    // a map that keeps duplicate keys
+    struct Map(Vec<(u8, u8)>);
+
+    impl ::std::iter::FromIterator<(u8, u8)> for Map {
+        fn from_iter<I: IntoIterator<Item = (u8, u8)>>(iter: I) -> Map {
+            Map(iter.into_iter().collect())
+        }
+    }
+
+    impl ::serde::Serialize for Map {
+        fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().cloned())
+        }
+    }
+
+    #[test]
+    fn non_canonical_test() {
+        // non-minimal integer, length and header markers
+        assert!(!is_canonical(&[0xcc, 0x05]).unwrap());
+        assert!(!is_canonical(&[0xd1, 0x01, 0x2c]).unwrap());
+        assert!(!is_canonical(&[0xd9, 0x01, 0x61]).unwrap());
+        assert!(!is_canonical(&[0xdc, 0x00, 0x01, 0x01]).unwrap());
+
+        // unsorted and duplicate keys
+        assert!(!is_canonical(&[0x82, 0xa1, 0x62, 0x01, 0xa1, 0x61, 0x02]).unwrap());
+        assert!(!is_canonical(&[0x82, 0xa1, 0x61, 0x01, 0xa1, 0x61, 0x02]).unwrap());
+
+        // a NaN with a payload
+        assert!(!is_canonical(&[0xca, 0x7f, 0xc0, 0x00, 0x01]).unwrap());
+
+        // trailing data
+        assert!(!is_canonical(&[0x01, 0x02]).unwrap());
+    }
+
+    #[test]
+    fn malformed_test() {
+        assert!(is_canonical(&[0x92, 0x01]).is_err());
+        assert!(is_canonical(&[0xc1]).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_test() {
+        let mut bytes = vec![0x91; 1_000_000];
+        bytes.push(0x00);
+        assert!(is_canonical(&bytes).unwrap());
+
+        // maps keep their own key order at every level
+        let mut bytes = vec![];
+        for _ in 0..100_000 {
+            bytes.extend_from_slice(&[0x82, 0xa1, 0x62, 0x01, 0xa1, 0x61]);
+        }
+        bytes.push(0x00);
+        assert!(!is_canonical(&bytes).unwrap());
+
+        assert!(is_canonical(&[0x91; 1_000_000]).is_err());
+    }
+
+    #[test]
+    fn default_output_test() {
+        // the default serializer uses the signed markers for this
+        assert!(!is_canonical(&::to_bytes(300i64).unwrap()).unwrap());
+        assert!(is_canonical(&canonical(300i64)).unwrap());
+        assert!(is_canonical(&canonical(-300i64)).unwrap());
+    }
+}
@@ -6,14 +6,16 @@
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
 use std::marker::PhantomData;
+use std::mem;
 
-use std::str;
+use std::str::{self, Utf8Error};
 
 use byteorder::{ByteOrder, BigEndian};
 
-use serde::Deserialize;
-
 use serde;
 
 use seq_deserializer::*;
@@ -22,26 +24,227 @@ use variant_deserializer::*;
 
 use defs::*;
 use error::Error;
-use read::{Read, Reference};
+use read::{Read, Reference, SliceRead};
 
 /// The corepack Deserializer struct. Contains a closure that should produce
 /// the next slice of data of the given length
 pub struct Deserializer<'de, R: Read<'de>> {
     read: R,
     scratch: Vec<u8>,
+    config: DeserializerConfig,
     phantom: PhantomData<&'de u8>,
 }
 
+/// A saved position in the input, returned by `Deserializer::checkpoint`.
+#[derive(Debug)]
+pub struct Checkpoint {
+    depth: usize,
+}
+
+/// Options that change what the Deserializer accepts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeserializerConfig {
+    legacy: bool,
+    human_readable: bool,
+    lenient: bool,
+    strict: bool,
+    reject_duplicate_keys: bool,
+    utf8: Utf8Policy,
+    bare_options: bool,
+}
+
+/// What to do with str values that aren't valid UTF-8.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Utf8Policy {
+    /// Fail with `Error::Utf8Error`.
+    #[default]
+    Strict,
+
+    /// Replace invalid sequences with U+FFFD, producing an owned string.
+    Lossy,
+
+    /// Pass the data to `visit_bytes`, and let the type being deserialized decide.
+    AsBytes,
+}
+
+impl DeserializerConfig {
+    /// The default options.
+    pub fn new() -> DeserializerConfig {
+        DeserializerConfig::default()
+    }
+
+    /// Accept data from encoders that follow the original messagepack spec.
+    ///
+    /// The old spec wrote strings and byte buffers with the same raw markers, so string fields
+    /// accept BIN data as long as it is valid UTF-8, and byte fields accept strings.
+    pub fn legacy(mut self, legacy: bool) -> DeserializerConfig {
+        self.legacy = legacy;
+        self
+    }
+
+    /// Read None from nil and Some from any other value, as `SerializerConfig::bare_options`
+    /// writes them.
+    ///
+    /// The usual `[false]` and `[true, value]` forms are no longer recognized, since they are
+    /// also valid values.
+    pub fn bare_options(mut self, bare_options: bool) -> DeserializerConfig {
+        self.bare_options = bare_options;
+        self
+    }
+
+    /// Report the format as human readable to the types being deserialized.
+    ///
+    /// This has to match the option the data was serialized with.
+    pub fn human_readable(mut self, human_readable: bool) -> DeserializerConfig {
+        self.human_readable = human_readable;
+        self
+    }
+
+    /// Convert values to the type a field asks for, instead of failing with `BadType`.
+    ///
+    /// * integer fields accept floats with no fractional part, and strings like "-12"
+    /// * float fields accept strings like "1.5", as well as integers, which they always do
+    /// * bool fields accept the integers 0 and 1, and the strings "true", "false", "1" and "0"
+    /// * string fields accept BIN data that is valid UTF-8, and byte fields accept strings
+    ///
+    /// Anything that would lose information, like 1.5 for an integer field, is still an error.
+    /// Turning this on turns `strict` off, since the two contradict each other.
+    pub fn lenient(mut self, lenient: bool) -> DeserializerConfig {
+        self.lenient = lenient;
+        self.strict = self.strict && !lenient;
+        self
+    }
+
+    /// Refuse conversions that could silently narrow a value, with `Error::TypeMismatch`.
+    ///
+    /// * integer fields refuse markers wider than their type, like UINT16 for a u8, even when
+    ///   the value would fit
+    /// * f32 fields refuse FLOAT64 values that would round, and float fields refuse integers
+    ///   they can't hold exactly
+    /// * string fields refuse BIN data, and byte fields refuse strings
+    /// * enums refuse variant indices that aren't integers, and ones past their last variant,
+    ///   even with `#[serde(other)]`
+    ///
+    /// Turning this on turns `lenient` off, since the two contradict each other.
+    pub fn strict(mut self, strict: bool) -> DeserializerConfig {
+        self.strict = strict;
+        self.lenient = self.lenient && !strict;
+        self
+    }
+
+    /// Fail with `Error::DuplicateKey` when a key appears twice in the same map.
+    ///
+    /// Keys are compared by their encoded bytes, so this also catches repeated struct fields,
+    /// including ones the struct would otherwise ignore.
+    pub fn reject_duplicate_keys(mut self, reject_duplicate_keys: bool) -> DeserializerConfig {
+        self.reject_duplicate_keys = reject_duplicate_keys;
+        self
+    }
+
+    /// Choose how to handle str values that aren't valid UTF-8. The default is `Strict`.
+    pub fn utf8_policy(mut self, utf8: Utf8Policy) -> DeserializerConfig {
+        self.utf8 = utf8;
+        self
+    }
+}
+
+// an integer read from the stream, in the family its marker used
+#[derive(Clone, Copy, Debug)]
+enum Integer {
+    Unsigned(u64),
+    Signed(i64),
+}
+
+impl Integer {
+    fn to_f64(self) -> f64 {
+        match self {
+            Integer::Unsigned(v) => v as f64,
+            Integer::Signed(v) => v as f64,
+        }
+    }
+
+    fn to_f32(self) -> f32 {
+        match self {
+            Integer::Unsigned(v) => v as f32,
+            Integer::Signed(v) => v as f32,
+        }
+    }
+
+    // true if f has exactly this value
+    fn equals(self, f: f64) -> bool {
+        match (self, integral(f)) {
+            (Integer::Unsigned(a), Some(Integer::Unsigned(b))) => a == b,
+            (Integer::Signed(a), Some(Integer::Signed(b))) => a == b,
+            (Integer::Signed(a), Some(Integer::Unsigned(b))) => a >= 0 && a as u64 == b,
+            _ => false,
+        }
+    }
+}
+
 impl<'de, R: Read<'de>> Deserializer<'de, R> {
     /// Create a new Deserializer given an input function.
     pub fn new(read: R) -> Deserializer<'de, R> {
+        Deserializer::with_config(read, DeserializerConfig::default())
+    }
+
+    /// Create a new Deserializer that uses the given options.
+    pub fn with_config(read: R, config: DeserializerConfig) -> Deserializer<'de, R> {
         Deserializer {
             read: read,
             scratch: vec![],
+            config,
             phantom: PhantomData,
         }
     }
 
+    /// Use scratch as the buffer for copied data, so a buffer can be kept across Deserializers.
+    ///
+    /// Its contents are discarded, but its allocation is reused.
+    pub fn with_scratch(mut self, mut scratch: Vec<u8>) -> Deserializer<'de, R> {
+        scratch.clear();
+        self.scratch = scratch;
+        self
+    }
+
+    /// Take the buffer used for copied data, leaving an empty one in its place.
+    pub fn take_scratch(&mut self) -> Vec<u8> {
+        mem::take(&mut self.scratch)
+    }
+
+    /// Start reading from read, keeping the options and scratch buffer, and return the old
+    /// reader.
+    ///
+    /// Checkpoints taken on the old reader are dropped along with it.
+    pub fn reset(&mut self, read: R) -> R {
+        mem::replace(&mut self.read, read)
+    }
+
+    /// Return the reader, dropping the Deserializer.
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+
+    /// Save the current position, to come back to it with `rewind`.
+    ///
+    /// Checkpoints can be nested. Hand every checkpoint to `rewind` or `release` once done with
+    /// it, since closure-based readers keep everything read after it until then.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint { depth: self.read.checkpoint() }
+    }
+
+    /// Go back to checkpoint, so the data after it is read again.
+    ///
+    /// This drops checkpoint and any taken after it. Fails with `BadCheckpoint` if checkpoint
+    /// was already dropped, or more was read since it than the reader's replay limit.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) -> Result<(), Error> {
+        self.read.rewind(checkpoint.depth)
+    }
+
+    /// Keep the current position, dropping checkpoint and any taken after it.
+    pub fn release(&mut self, checkpoint: Checkpoint) {
+        self.read.release(checkpoint.depth)
+    }
+
     #[inline]
     fn input<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a>, Error> {
         let result = self.read.input(len, &mut self.scratch)?;
@@ -50,19 +253,258 @@ impl<'de, R: Read<'de>> Deserializer<'de, R> {
     }
 
     #[inline]
-    fn parse_str<'a, V>(reference: Reference<'de, 'a>, visitor: V) -> Result<V::Value, Error>
+    fn peek(&mut self) -> Result<u8, Error> {
+        self.read.peek()
+    }
+
+    fn read_length(&mut self, bytes: usize) -> Result<usize, Error> {
+        let buf = self.input(bytes)?;
+
+        Ok(match bytes {
+            1 => buf[0] as usize,
+            U16_BYTES => BigEndian::read_u16(&buf) as usize,
+            _ => BigEndian::read_u32(&buf) as usize,
+        })
+    }
+
+    /// Read the integer with marker ty, or return None if ty is not an integer marker.
+    fn parse_integer(&mut self, ty: u8) -> Result<Option<Integer>, Error> {
+        Ok(Some(match ty {
+            v if POS_FIXINT.contains(v) => Integer::Unsigned(v as u64),
+            v if NEG_FIXINT.contains(v) => Integer::Signed(read_signed(v) as i64),
+            UINT8 => Integer::Unsigned(self.input(1)?[0] as u64),
+            UINT16 => Integer::Unsigned(BigEndian::read_u16(&self.input(U16_BYTES)?) as u64),
+            UINT32 => Integer::Unsigned(BigEndian::read_u32(&self.input(U32_BYTES)?) as u64),
+            UINT64 => Integer::Unsigned(BigEndian::read_u64(&self.input(U64_BYTES)?)),
+            INT8 => Integer::Signed(read_signed(self.input(1)?[0]) as i64),
+            INT16 => Integer::Signed(BigEndian::read_i16(&self.input(U16_BYTES)?) as i64),
+            INT32 => Integer::Signed(BigEndian::read_i32(&self.input(U32_BYTES)?) as i64),
+            INT64 => Integer::Signed(BigEndian::read_i64(&self.input(U64_BYTES)?)),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Read the float with marker ty, or return None if ty is not a float marker.
+    fn parse_float(&mut self, ty: u8) -> Result<Option<f64>, Error> {
+        Ok(Some(match ty {
+            FLOAT32 => BigEndian::read_f32(&self.input(U32_BYTES)?) as f64,
+            FLOAT64 => BigEndian::read_f64(&self.input(U64_BYTES)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Read the string with marker ty and convert it with f, or return None if ty is not a str
+    /// marker. Strings that f can't convert are a `BadType`, and invalid UTF-8 is handled by the
+    /// configured `Utf8Policy`.
+    fn parse_text<T, F>(&mut self, ty: u8, f: F) -> Result<Option<T>, Error>
+        where F: FnOnce(&str) -> Option<T>
+    {
+        if !FIXSTR.contains(ty) && ty != STR8 && ty != STR16 && ty != STR32 {
+            return Ok(None);
+        }
+
+        let utf8 = self.config.utf8;
+        let reference = match self.read_blob(ty)? {
+            Some(reference) => reference,
+            None => return Ok(None),
+        };
+
+        let value = match str::from_utf8(&reference) {
+            Ok(text) => f(text),
+            Err(e) => {
+                match utf8 {
+                    Utf8Policy::Strict => return Err(e.into()),
+                    Utf8Policy::Lossy => f(&String::from_utf8_lossy(&reference)),
+                    // the text is only ever converted to numbers and bools, which bytes aren't
+                    Utf8Policy::AsBytes => None,
+                }
+            }
+        };
+
+        value.map(Some).ok_or(Error::BadType)
+    }
+
+    /// Deserialize an integer for a field of type expected, which is width bytes wide.
+    fn parse_integer_hint<V>(&mut self,
+                             visitor: V,
+                             expected: &'static str,
+                             width: usize)
+                             -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        let ty = self.input(1)?[0];
+
+        if self.config.strict && integer_width(ty).is_some_and(|found| found > width) {
+            return Err(mismatch(ty, expected));
+        }
+
+        if self.config.lenient {
+            if let Some(v) = self.parse_float(ty)? {
+                return visit_integer(visitor, integral(v).ok_or(Error::BadType)?);
+            }
+
+            let text = self.parse_text(ty, |text| {
+                    text.parse()
+                        .map(Integer::Unsigned)
+                        .or_else(|_| text.parse().map(Integer::Signed))
+                        .ok()
+                })?;
+            if let Some(v) = text {
+                return visit_integer(visitor, v);
+            }
+        }
+
+        self.parse_as(visitor, ty)
+    }
+
+    /// Read the payload of a str or bin value, or return None if ty is neither.
+    fn read_blob<'a>(&'a mut self, ty: u8) -> Result<Option<Reference<'de, 'a>>, Error> {
+        let size = match ty {
+            v if FIXSTR.contains(v) => (v & !FIXSTR_MASK) as usize,
+            STR8 | BIN8 => self.read_length(1)?,
+            STR16 | BIN16 => self.read_length(U16_BYTES)?,
+            STR32 | BIN32 => self.read_length(U32_BYTES)?,
+            _ => return Ok(None),
+        };
+
+        self.input(size).map(Some)
+    }
+
+    /// Read the payload of a bin value, or return None if ty is not a bin marker.
+    fn read_bin<'a>(&'a mut self, ty: u8) -> Result<Option<Reference<'de, 'a>>, Error> {
+        // legacy encoders write bytes with the raw (string) markers
+        if ty != BIN8 && ty != BIN16 && ty != BIN32 && !self.config.legacy {
+            return Ok(None);
+        }
+
+        self.read_blob(ty)
+    }
+
+    /// Read the 16 bytes of a 128-bit integer that didn't fit in 64 bits, or return None if ty
+    /// doesn't start one.
+    fn read_wide(&mut self, ty: u8) -> Result<Option<[u8; U128_BYTES]>, Error> {
+        let reference = match self.read_bin(ty)? {
+            Some(reference) => reference,
+            None => return Ok(None),
+        };
+
+        if reference.len() != U128_BYTES {
+            return Err(Error::BadLength);
+        }
+
+        let mut buf = [0; U128_BYTES];
+        buf.copy_from_slice(&reference);
+        Ok(Some(buf))
+    }
+
+    /// Step over the next value in the stream without decoding it.
+    pub(crate) fn skip_value(&mut self) -> Result<(), Error> {
+        // number of values left to skip, including the items of containers
+        let mut remaining: usize = 1;
+
+        while remaining > 0 {
+            remaining -= 1;
+
+            let ty = self.input(1)?[0];
+            let (payload, items) = match ty {
+                v if POS_FIXINT.contains(v) || NEG_FIXINT.contains(v) => (0, 0),
+                v if FIXMAP.contains(v) => (0, (v & !FIXMAP_MASK) as usize * 2),
+                v if FIXARRAY.contains(v) => (0, (v & !FIXARRAY_MASK) as usize),
+                v if FIXSTR.contains(v) => ((v & !FIXSTR_MASK) as usize, 0),
+                NIL | FALSE | TRUE => (0, 0),
+                BIN8 | STR8 => (self.read_length(1)?, 0),
+                BIN16 | STR16 => (self.read_length(U16_BYTES)?, 0),
+                BIN32 | STR32 => (self.read_length(U32_BYTES)?, 0),
+                // ext payloads are preceded by their type byte
+                EXT8 => (self.read_length(1)? + 1, 0),
+                EXT16 => (self.read_length(U16_BYTES)? + 1, 0),
+                EXT32 => (self.read_length(U32_BYTES)?.checked_add(1).ok_or(Error::TooBig)?, 0),
+                UINT8 | INT8 => (1, 0),
+                UINT16 | INT16 => (U16_BYTES, 0),
+                UINT32 | INT32 | FLOAT32 => (U32_BYTES, 0),
+                UINT64 | INT64 | FLOAT64 => (U64_BYTES, 0),
+                FIXEXT1 => (2, 0),
+                FIXEXT2 => (3, 0),
+                FIXEXT4 => (5, 0),
+                FIXEXT8 => (9, 0),
+                FIXEXT16 => (17, 0),
+                ARRAY16 => (0, self.read_length(U16_BYTES)?),
+                ARRAY32 => (0, self.read_length(U32_BYTES)?),
+                MAP16 => (0, self.read_length(U16_BYTES)? * 2),
+                MAP32 => (0, self.read_length(U32_BYTES)?.checked_mul(2).ok_or(Error::TooBig)?),
+                _ => return Err(Error::BadType),
+            };
+
+            self.read.skip(payload)?;
+            remaining = remaining.checked_add(items).ok_or(Error::TooBig)?;
+        }
+
+        Ok(())
+    }
+
+    /// Step over the next value and return its encoded bytes.
+    fn capture_value<'a>(&'a mut self) -> Result<Reference<'de, 'a>, Error> {
+        self.capture(|de| de.skip_value()).map(|(_, captured)| captured)
+    }
+
+    /// Run f, and return its result along with the encoded bytes it consumed.
+    pub(crate) fn capture<'a, T, F>(&'a mut self, f: F) -> Result<(T, Reference<'de, 'a>), Error>
+        where F: FnOnce(&mut Self) -> Result<T, Error>
+    {
+        self.read.begin_capture();
+        let result = f(self);
+        let captured = self.read.end_capture(&mut self.scratch);
+
+        result.map(|value| (value, captured))
+    }
+
+    pub(crate) fn rejects_duplicate_keys(&self) -> bool {
+        self.config.reject_duplicate_keys
+    }
+
+    #[inline]
+    fn parse_str<'a, V>(reference: Reference<'de, 'a>,
+                        visitor: V,
+                        utf8: Utf8Policy)
+                        -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
         match reference {
             Reference::Borrowed(buf) => {
-                visitor.visit_borrowed_str(str::from_utf8(buf).map_err(|e| Into::<Error>::into(e))?)
+                match str::from_utf8(buf) {
+                    Ok(v) => visitor.visit_borrowed_str(v),
+                    Err(e) => {
+                        Deserializer::<'de, R>::parse_invalid_str(reference, e, visitor, utf8)
+                    }
+                }
             }
             Reference::Copied(buf) => {
-                visitor.visit_str(str::from_utf8(buf).map_err(|e| Into::<Error>::into(e))?)
+                match str::from_utf8(buf) {
+                    Ok(v) => visitor.visit_str(v),
+                    Err(e) => {
+                        Deserializer::<'de, R>::parse_invalid_str(reference, e, visitor, utf8)
+                    }
+                }
             }
         }
     }
 
+    fn parse_invalid_str<'a, V>(reference: Reference<'de, 'a>,
+                                error: Utf8Error,
+                                visitor: V,
+                                utf8: Utf8Policy)
+                                -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        match utf8 {
+            Utf8Policy::Strict => Err(error.into()),
+            Utf8Policy::Lossy => {
+                visitor.visit_string(String::from_utf8_lossy(&reference).into_owned())
+            }
+            Utf8Policy::AsBytes => Deserializer::<'de, R>::parse_bytes(reference, visitor),
+        }
+    }
+
     #[inline]
     fn parse_bytes<'a, V>(reference: Reference<'de, 'a>, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
@@ -73,6 +515,88 @@ impl<'de, R: Read<'de>> Deserializer<'de, R> {
         }
     }
 
+    fn parse_seq<V>(&mut self, visitor: V, ty: u8) -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        // byte containers may have been written as bin, so hand those out one byte at a time
+        if let Some(reference) = self.read_bin(ty)? {
+            let mut seq = serde::de::value::SeqDeserializer::<_, Error>::new(reference.iter()
+                .cloned());
+            let value = visitor.visit_seq(&mut seq)?;
+            seq.end()?;
+            return Ok(value);
+        }
+
+        self.parse_as(visitor, ty)
+    }
+
+    fn visit_seq_of<V>(&mut self, visitor: V, count: usize) -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        let mut seq = SeqDeserializer::new(self, count);
+        let value = visitor.visit_seq(&mut seq)?;
+
+        // elements the visitor didn't read would be misread as the values after this one
+        seq.end()?;
+        Ok(value)
+    }
+
+    fn visit_map_of<V>(&mut self, visitor: V, count: usize) -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        let mut map = SeqDeserializer::new(self, count);
+        let value = visitor.visit_map(&mut map)?;
+
+        map.end()?;
+        Ok(value)
+    }
+
+    /// Read the start of an enum and deserialize its variant with seed.
+    ///
+    /// Enums may be written as `[index, value]`, `[name, value]` or `{name: value}`, and unit
+    /// variants also as a bare index or name. Returns whether a value follows.
+    pub(crate) fn parse_variant<S>(&mut self,
+                                   seed: S,
+                                   variants: &'static [&'static str])
+                                   -> Result<(S::Value, bool), Error>
+        where S: serde::de::DeserializeSeed<'de>
+    {
+        let ty = self.peek()?;
+
+        let payload = if ty == 2 | FIXARRAY_MASK || ty == 1 | FIXMAP_MASK {
+            self.input(1)?;
+            true
+        } else if FIXARRAY.contains(ty) || FIXMAP.contains(ty) {
+            return Err(Error::BadLength);
+        } else {
+            false
+        };
+
+        let ty = self.peek()?;
+
+        // names go to the seed as they are, like any other identifier
+        if FIXSTR.contains(ty) || ty == STR8 || ty == STR16 || ty == STR32 {
+            return seed.deserialize(&mut *self).map(|value| (value, payload));
+        }
+
+        if self.config.strict && integer_width(ty).is_none() {
+            return Err(mismatch(ty, "variant index"));
+        }
+
+        let index: u64 = serde::Deserialize::deserialize(&mut *self)?;
+
+        if self.config.strict && index >= variants.len() as u64 {
+            return Err(serde::de::Error::invalid_value(serde::de::Unexpected::Unsigned(index),
+                                                       &"a known variant index"));
+        }
+
+        // hand over the index itself, so unknown variants reach #[serde(other)] or get the
+        // usual unknown variant error
+        let de: serde::de::value::U64Deserializer<Error> =
+            serde::de::IntoDeserializer::into_deserializer(index);
+        seed.deserialize(de).map(|value| (value, payload))
+    }
+
     fn parse_as<V>(&mut self, visitor: V, ty: u8) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
@@ -81,16 +605,17 @@ impl<'de, R: Read<'de>> Deserializer<'de, R> {
             v if NEG_FIXINT.contains(v) => visitor.visit_i8(read_signed(v)),
             v if FIXMAP.contains(v) => {
                 let size = (v & !FIXMAP_MASK) as usize * 2;
-                visitor.visit_map(SeqDeserializer::new(self, size))
+                self.visit_map_of(visitor, size)
             }
             v if FIXARRAY.contains(v) => {
                 let size = (v & !FIXARRAY_MASK) as usize;
-                visitor.visit_seq(SeqDeserializer::new(self, size))
+                self.visit_seq_of(visitor, size)
             }
             v if FIXSTR.contains(v) => {
+                let utf8 = self.config.utf8;
                 let reference = self.input((v & !FIXSTR_MASK) as usize)?;
 
-                Deserializer::<'de, R>::parse_str(reference, visitor)
+                Deserializer::<'de, R>::parse_str(reference, visitor, utf8)
             }
             NIL => visitor.visit_unit(),
             FALSE => visitor.visit_bool(false),
@@ -208,48 +733,97 @@ impl<'de, R: Read<'de>> Deserializer<'de, R> {
                 visitor.visit_map(ExtDeserializer::new(ty, &buf))
             }
             STR8 => {
+                let utf8 = self.config.utf8;
                 let size = self.input(1)?[0] as usize;
 
                 let buf = self.input(size)?;
-                Deserializer::<'de, R>::parse_str(buf, visitor)
+                Deserializer::<'de, R>::parse_str(buf, visitor, utf8)
             }
             STR16 => {
+                let utf8 = self.config.utf8;
                 let size = BigEndian::read_u16(&self.input(U16_BYTES)?) as usize;
 
                 let buf = self.input(size)?;
-                Deserializer::<'de, R>::parse_str(buf, visitor)
+                Deserializer::<'de, R>::parse_str(buf, visitor, utf8)
             }
             STR32 => {
-                let size = BigEndian::read_u16(&self.input(U32_BYTES)?) as usize;
+                let utf8 = self.config.utf8;
+                let size = BigEndian::read_u32(&self.input(U32_BYTES)?) as usize;
 
                 let buf = self.input(size)?;
-                Deserializer::<'de, R>::parse_str(buf, visitor)
+                Deserializer::<'de, R>::parse_str(buf, visitor, utf8)
             }
             ARRAY16 => {
                 let size = BigEndian::read_u16(&self.input(U16_BYTES)?);
 
-                visitor.visit_seq(SeqDeserializer::new(self, size as usize))
+                self.visit_seq_of(visitor, size as usize)
             }
             ARRAY32 => {
                 let size = BigEndian::read_u32(&self.input(U32_BYTES)?);
 
-                visitor.visit_seq(SeqDeserializer::new(self, size as usize))
+                self.visit_seq_of(visitor, size as usize)
             }
             MAP16 => {
                 let size = BigEndian::read_u16(&self.input(U16_BYTES)?);
 
-                visitor.visit_map(SeqDeserializer::new(self, size as usize * 2))
+                self.visit_map_of(visitor, size as usize * 2)
             }
             MAP32 => {
                 let size = BigEndian::read_u32(&self.input(U32_BYTES)?);
 
-                visitor.visit_map(SeqDeserializer::new(self, size as usize * 2))
+                self.visit_map_of(visitor, size as usize * 2)
             }
             _ => Err(Error::BadType),
         }
     }
 }
 
+fn visit_integer<'de, V>(visitor: V, v: Integer) -> Result<V::Value, Error>
+    where V: serde::de::Visitor<'de>
+{
+    match v {
+        Integer::Unsigned(v) => visitor.visit_u64(v),
+        Integer::Signed(v) => visitor.visit_i64(v),
+    }
+}
+
+// the integer equal to v, if there is one
+fn integral(v: f64) -> Option<Integer> {
+    if (-9_223_372_036_854_775_808.0..0.0).contains(&v) && v as i64 as f64 == v {
+        Some(Integer::Signed(v as i64))
+    } else if (0.0..18_446_744_073_709_551_616.0).contains(&v) && v as u64 as f64 == v {
+        Some(Integer::Unsigned(v as u64))
+    } else {
+        None
+    }
+}
+
+// the number of bytes an integer marker holds its value in, or None for other markers
+fn integer_width(ty: u8) -> Option<usize> {
+    match ty {
+        v if POS_FIXINT.contains(v) || NEG_FIXINT.contains(v) => Some(1),
+        UINT8 | INT8 => Some(1),
+        UINT16 | INT16 => Some(U16_BYTES),
+        UINT32 | INT32 => Some(U32_BYTES),
+        UINT64 | INT64 => Some(U64_BYTES),
+        _ => None,
+    }
+}
+
+fn mismatch(ty: u8, expected: &'static str) -> Error {
+    Error::TypeMismatch {
+        found: marker_name(ty),
+        expected,
+    }
+}
+
+/// Returns the encoded length of the value at the start of bytes.
+pub(crate) fn value_len(bytes: &[u8]) -> Result<usize, Error> {
+    let mut de = Deserializer::new(SliceRead::new(bytes));
+    de.skip_value()?;
+    Ok(de.read.position())
+}
+
 impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'de, R> {
     type Error = Error;
 
@@ -264,73 +838,181 @@ impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'d
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        let ty = self.input(1)?[0];
+
+        if self.config.lenient {
+            match ty {
+                0 => return visitor.visit_bool(false),
+                1 => return visitor.visit_bool(true),
+                _ => {}
+            }
+
+            let text = self.parse_text(ty, |text| match text {
+                    "true" | "1" => Some(true),
+                    "false" | "0" => Some(false),
+                    _ => None,
+                })?;
+            if let Some(v) = text {
+                return visitor.visit_bool(v);
+            }
+        }
+
+        self.parse_as(visitor, ty)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        self.parse_integer_hint(visitor, "u64", U64_BYTES)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_u64(visitor)
+        self.parse_integer_hint(visitor, "u8", 1)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_u64(visitor)
+        self.parse_integer_hint(visitor, "u16", U16_BYTES)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_u64(visitor)
+        self.parse_integer_hint(visitor, "u32", U32_BYTES)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        self.parse_integer_hint(visitor, "i64", U64_BYTES)
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_i64(visitor)
+        self.parse_integer_hint(visitor, "i8", 1)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_i64(visitor)
+        self.parse_integer_hint(visitor, "i16", U16_BYTES)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_i64(visitor)
+        self.parse_integer_hint(visitor, "i32", U32_BYTES)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        let ty = self.input(1)?[0];
+
+        if let Some(buf) = self.read_wide(ty)? {
+            return visitor.visit_i128(i128::from_be_bytes(buf));
+        }
+
+        match self.parse_integer(ty)? {
+            Some(Integer::Unsigned(v)) => visitor.visit_i128(v as i128),
+            Some(Integer::Signed(v)) => visitor.visit_i128(v as i128),
+            None => self.parse_as(visitor, ty),
+        }
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: serde::de::Visitor<'de>
+    {
+        let ty = self.input(1)?[0];
+
+        if let Some(buf) = self.read_wide(ty)? {
+            return visitor.visit_u128(u128::from_be_bytes(buf));
+        }
+
+        match self.parse_integer(ty)? {
+            Some(Integer::Unsigned(v)) => visitor.visit_u128(v as u128),
+            // leave negative values for the visitor to reject
+            Some(Integer::Signed(v)) if v < 0 => visitor.visit_i64(v),
+            Some(Integer::Signed(v)) => visitor.visit_u128(v as u128),
+            None => self.parse_as(visitor, ty),
+        }
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        let ty = self.input(1)?[0];
+
+        // integral floats may have been written as integers
+        if let Some(v) = self.parse_integer(ty)? {
+            if self.config.strict && !v.equals(v.to_f64()) {
+                return Err(mismatch(ty, "f64"));
+            }
+
+            return visitor.visit_f64(v.to_f64());
+        }
+
+        if self.config.lenient {
+            if let Some(v) = self.parse_text(ty, |text| text.parse().ok())? {
+                return visitor.visit_f64(v);
+            }
+        }
+
+        self.parse_as(visitor, ty)
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_f64(visitor)
+        let ty = self.input(1)?[0];
+
+        if let Some(v) = self.parse_integer(ty)? {
+            if self.config.strict && !v.equals(v.to_f32() as f64) {
+                return Err(mismatch(ty, "f32"));
+            }
+
+            return visitor.visit_f32(v.to_f32());
+        }
+
+        if self.config.strict && ty == FLOAT64 {
+            let v = BigEndian::read_f64(&self.input(U64_BYTES)?);
+
+            // NaNs don't compare equal, but they don't lose anything interesting either
+            if v as f32 as f64 != v && !v.is_nan() {
+                return Err(mismatch(ty, "f32"));
+            }
+
+            return visitor.visit_f32(v as f32);
+        }
+
+        if self.config.lenient {
+            if let Some(v) = self.parse_text(ty, |text| text.parse().ok())? {
+                return visitor.visit_f32(v);
+            }
+        }
+
+        self.parse_as(visitor, ty)
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        let ty = self.input(1)?[0];
+
+        if self.config.legacy || self.config.lenient {
+            let utf8 = self.config.utf8;
+            if let Some(reference) = self.read_blob(ty)? {
+                return Deserializer::<'de, R>::parse_str(reference, visitor, utf8);
+            }
+        } else if self.config.strict && (ty == BIN8 || ty == BIN16 || ty == BIN32) {
+            return Err(mismatch(ty, "str"));
+        }
+
+        self.parse_as(visitor, ty)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -354,34 +1036,61 @@ impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'d
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        // hack below
-        let (is_some /* maybe value */,) = Deserialize::deserialize(&mut *self)?;
+        if self.config.bare_options {
+            if self.peek()? == NIL {
+                self.input(1)?;
+                return visitor.visit_none();
+            }
 
-        if is_some {
-            // This works because there are no terminating sequences for tuples or the like
-            visitor.visit_some(self)
-            // otherwise cleanup would be required here
-        } else {
-            visitor.visit_none()
+            return visitor.visit_some(self);
+        }
+
+        // options are written as [false] or [true, value]
+        let ty = self.input(1)?[0];
+        if !FIXARRAY.contains(ty) {
+            return Err(Error::BadType);
+        }
+
+        let size = (ty & !FIXARRAY_MASK) as usize;
+        let is_some = self.input(1)?[0];
+
+        match (size, is_some) {
+            (1, FALSE) => visitor.visit_none(),
+            (2, TRUE) => visitor.visit_some(self),
+            (_, FALSE) | (_, TRUE) => Err(Error::BadLength),
+            _ => Err(Error::BadType),
         }
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        let ty = self.input(1)?[0];
+
+        self.parse_seq(visitor, ty)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        let ty = self.input(1)?[0];
+
+        if self.config.legacy || self.config.lenient {
+            if let Some(reference) = self.read_blob(ty)? {
+                return Deserializer::<'de, R>::parse_bytes(reference, visitor);
+            }
+        } else if self.config.strict &&
+                  (FIXSTR.contains(ty) || ty == STR8 || ty == STR16 || ty == STR32) {
+            return Err(mismatch(ty, "bytes"));
+        }
+
+        self.parse_as(visitor, ty)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        self.deserialize_bytes(visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -396,9 +1105,18 @@ impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'d
         self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _: &'static str, visitor: V) -> Result<V::Value, Error>
+    fn deserialize_newtype_struct<V>(self,
+                                     name: &'static str,
+                                     visitor: V)
+                                     -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
+        if name == RAW_TOKEN {
+            let reference = self.capture_value()?;
+
+            return Deserializer::<'de, R>::parse_bytes(reference, visitor);
+        }
+
         self.deserialize_any(visitor)
     }
 
@@ -422,10 +1140,23 @@ impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'d
         self.deserialize_map(visitor)
     }
 
-    fn deserialize_tuple<V>(self, _: usize, visitor: V) -> Result<V::Value, Error>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_seq(visitor)
+        let ty = self.input(1)?[0];
+
+        let count = match ty {
+            v if FIXARRAY.contains(v) => (v & !FIXARRAY_MASK) as usize,
+            ARRAY16 => self.read_length(U16_BYTES)?,
+            ARRAY32 => self.read_length(U32_BYTES)?,
+            _ => return self.parse_seq(visitor, ty),
+        };
+
+        if count != len {
+            return Err(Error::BadLength);
+        }
+
+        self.visit_seq_of(visitor, count)
     }
 
     fn deserialize_enum<V>(self,
@@ -441,7 +1172,8 @@ impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'d
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
         where V: serde::de::Visitor<'de>
     {
-        self.deserialize_any(visitor)
+        self.skip_value()?;
+        visitor.visit_unit()
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -449,12 +1181,20 @@ impl<'de, 'a, R: Read<'de>> serde::Deserializer<'de> for &'a mut Deserializer<'d
     {
         self.deserialize_any(visitor)
     }
+
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::BTreeMap;
 
+    use error::Error;
+
+    use super::Deserializer;
+
     #[test]
     fn positive_fixint_test() {
         let value: u8 = ::from_bytes(&[0x17]).unwrap();
@@ -526,4 +1266,762 @@ mod test {
         assert_eq!(map.remove(&format!("three")), Some(3));
         assert!(map.is_empty());
     }
+
+    #[test]
+    fn nil_option_test() {
+        // a bare nil is only an option with DeserializerConfig::bare_options
+        assert!(::from_bytes::<Option<u8>>(&[0xc0]).is_err());
+    }
+
+    #[test]
+    fn option_from_iter_test() {
+        let value: Option<u8> = ::from_iter(vec![0x92, 0xc3, 0x07].into_iter()).unwrap();
+        assert_eq!(value, Some(7));
+
+        let value: Option<u8> = ::from_iter(vec![0x91, 0xc2].into_iter()).unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn bad_option_length_test() {
+        assert!(::from_bytes::<Option<u8>>(&[0x91, 0xc3]).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Known {
+        a: u8,
+    }
+
+    fn unknown_fields_fixture() -> Vec<u8> {
+        let long = [0x78; 100];
+        let mut fixture = vec![0x84, // map with four entries
+                               0xa1, 0x62, // 'b': array of a str8, a map and a fixext
+                               0x93,
+                               0xd9, long.len() as u8];
+        fixture.extend_from_slice(&long);
+        fixture.extend_from_slice(&[0x81, 0x01, 0xc0, 0xd4, 0x05, 0x2a]);
+        fixture.extend_from_slice(&[0xa1, 0x61, 0x07]); // 'a': 7
+        fixture.extend_from_slice(&[0xa1, 0x63, 0xc6, 0x00, 0x00, 0x00, long.len() as u8]);
+        fixture.extend_from_slice(&long); // 'c': bin32
+        fixture.extend_from_slice(&[0xa1, 0x64, 0xc7, 0x02, 0x01, 0xff, 0xff]); // 'd': ext8
+        fixture
+    }
+
+    #[test]
+    fn ignored_any_test() {
+        let fixture = unknown_fields_fixture();
+        let value: Known = ::from_bytes(&fixture).unwrap();
+        assert_eq!(value, Known { a: 7 });
+    }
+
+    #[test]
+    fn ignored_any_from_iter_test() {
+        let fixture = unknown_fields_fixture();
+        let value: Known = ::from_iter(fixture.into_iter()).unwrap();
+        assert_eq!(value, Known { a: 7 });
+    }
+
+    #[test]
+    fn ignored_any_truncated_test() {
+        let mut fixture = unknown_fields_fixture();
+        fixture.truncate(20);
+        assert!(::from_bytes::<Known>(&fixture).is_err());
+    }
+
+    // a float that only accepts visit_f64
+    #[derive(Debug, PartialEq)]
+    struct OnlyFloat(f64);
+
+    impl<'de> ::serde::Deserialize<'de> for OnlyFloat {
+        fn deserialize<D>(deserializer: D) -> Result<OnlyFloat, D::Error>
+            where D: ::serde::Deserializer<'de>
+        {
+            struct Visitor;
+
+            impl<'de> ::serde::de::Visitor<'de> for Visitor {
+                type Value = OnlyFloat;
+
+                fn expecting(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    fmt.write_str("a float")
+                }
+
+                fn visit_f64<E>(self, v: f64) -> Result<OnlyFloat, E> {
+                    Ok(OnlyFloat(v))
+                }
+            }
+
+            deserializer.deserialize_f64(Visitor)
+        }
+    }
+
+    #[test]
+    fn float_from_integer_test() {
+        assert_eq!(::from_bytes::<OnlyFloat>(&[0x07]).unwrap(), OnlyFloat(7.0));
+        assert_eq!(::from_bytes::<OnlyFloat>(&[0xd1, 0xfe, 0xd4]).unwrap(), OnlyFloat(-300.0));
+        assert_eq!(::from_bytes::<f32>(&[0xcd, 0x01, 0x2c]).unwrap(), 300.0);
+    }
+
+    #[test]
+    fn legacy_test() {
+        let config = ::DeserializerConfig::new().legacy(true);
+
+        // a string written with BIN8, and bytes written as a fixstr
+        assert_eq!(::from_bytes_with_config::<&str>(&[0xc4, 0x02, 0x68, 0x69], config).unwrap(),
+                   "hi");
+        assert_eq!(::from_bytes_with_config::<&[u8]>(&[0xa2, 0x68, 0x69], config).unwrap(),
+                   b"hi");
+
+        // BIN data still has to be valid UTF-8 to be a string
+        assert!(::from_bytes_with_config::<&str>(&[0xc4, 0x01, 0xff], config).is_err());
+
+        // a char only accepts strings, so this needs legacy mode
+        assert_eq!(::from_bytes_with_config::<char>(&[0xc4, 0x01, 0x61], config).unwrap(),
+                   'a');
+        assert!(::from_bytes::<char>(&[0xc4, 0x01, 0x61]).is_err());
+    }
+
+    #[test]
+    fn int128_test() {
+        let values = [0, -1, i64::MIN as i128, u64::MAX as i128, i128::MIN, i128::MAX];
+        for &value in &values {
+            assert_eq!(::from_bytes::<i128>(&::to_bytes(value).unwrap()).unwrap(), value);
+        }
+
+        let values = [0, 300, u64::MAX as u128, u128::MAX];
+        for &value in &values {
+            assert_eq!(::from_bytes::<u128>(&::to_bytes(value).unwrap()).unwrap(), value);
+        }
+
+        assert!(::from_bytes::<u128>(&[0xff]).is_err());
+        assert!(::from_bytes::<u128>(&[0xc4, 0x02, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn bin_as_seq_test() {
+        let bytes = &[0xc4, 0x03, 0x01, 0x02, 0x03];
+
+        assert_eq!(::from_bytes::<Vec<u8>>(bytes).unwrap(), vec![1, 2, 3]);
+        assert_eq!(::from_bytes::<[u8; 3]>(bytes).unwrap(), [1, 2, 3]);
+        assert_eq!(::from_iter::<_, Vec<u8>>(bytes.iter().cloned()).unwrap(), vec![1, 2, 3]);
+        assert!(::from_bytes::<[u8; 2]>(bytes).is_err());
+    }
+
+    #[test]
+    fn lenient_test() {
+        let config = ::DeserializerConfig::new().lenient(true);
+
+        assert_eq!(::from_bytes_with_config::<u8>(&[0xcb, 0x40, 0x08, 0, 0, 0, 0, 0, 0], config)
+                       .unwrap(),
+                   3);
+        assert_eq!(::from_bytes_with_config::<i32>(&[0xa3, 0x2d, 0x31, 0x32], config).unwrap(),
+                   -12);
+        assert_eq!(::from_bytes_with_config::<f64>(&[0xa3, 0x31, 0x2e, 0x35], config).unwrap(),
+                   1.5);
+        assert!(::from_bytes_with_config::<bool>(&[0xa1, 0x31], config).unwrap());
+        assert!(!::from_bytes_with_config::<bool>(&[0x00], config).unwrap());
+        assert_eq!(::from_bytes_with_config::<char>(&[0xc4, 0x01, 0x61], config).unwrap(),
+                   'a');
+
+        // lossy conversions are still errors
+        assert!(::from_bytes_with_config::<u8>(&[0xca, 0x3f, 0xc0, 0, 0], config).is_err());
+        assert!(::from_bytes_with_config::<u8>(&[0xa3, 0x33, 0x30, 0x30], config).is_err());
+        assert!(::from_bytes_with_config::<bool>(&[0x02], config).is_err());
+        assert!(::from_bytes_with_config::<bool>(&[0xa3, 0x79, 0x65, 0x73], config).is_err());
+
+        // and none of this happens by default
+        assert!(::from_bytes::<u8>(&[0xcb, 0x40, 0x08, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(::from_bytes::<bool>(&[0xa1, 0x31]).is_err());
+    }
+
+    fn strict<'a, V: ::serde::Deserialize<'a>>(bytes: &'a [u8]) -> Result<V, Error> {
+        ::from_bytes_with_config(bytes, ::DeserializerConfig::new().strict(true))
+    }
+
+    #[test]
+    fn strict_integer_test() {
+        assert_eq!(strict::<u8>(&[0xcc, 0xff]).unwrap(), 255);
+        assert_eq!(strict::<u16>(&[0xcc, 0xff]).unwrap(), 255);
+        assert_eq!(strict::<i64>(&[0xd1, 0xfe, 0xd4]).unwrap(), -300);
+
+        match strict::<u8>(&[0xcd, 0x00, 0x01]) {
+            Err(Error::TypeMismatch { found: "uint16", expected: "u8" }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert!(strict::<i16>(&[0xd2, 0x00, 0x00, 0x00, 0x01]).is_err());
+        assert_eq!(::from_bytes::<u8>(&[0xcd, 0x00, 0x01]).unwrap(), 1);
+    }
+
+    #[test]
+    fn strict_float_test() {
+        assert_eq!(strict::<f32>(&[0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]).unwrap(), 1.5);
+        assert_eq!(strict::<f64>(&[0xcf, 0x00, 0x20, 0, 0, 0, 0, 0, 0]).unwrap(),
+                   9007199254740992.0);
+
+        // 0.1 isn't the same number as an f32
+        match strict::<f32>(&[0xcb, 0x3f, 0xb9, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]) {
+            Err(Error::TypeMismatch { found: "float64", expected: "f32" }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // neither is 2^53 + 1 as an f64, or 2^24 + 1 as an f32
+        assert!(strict::<f64>(&[0xcf, 0x00, 0x20, 0, 0, 0, 0, 0, 1]).is_err());
+        assert!(strict::<f32>(&[0xce, 0x01, 0x00, 0x00, 0x01]).is_err());
+        assert!(strict::<f32>(&[0xcb, 0x7f, 0xf8, 0, 0, 0, 0, 0, 0]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn strict_string_test() {
+        match strict::<&str>(&[0xc4, 0x02, 0x68, 0x69]) {
+            Err(Error::TypeMismatch { found: "bin8", expected: "str" }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let error = strict::<&[u8]>(&[0xa2, 0x68, 0x69]).unwrap_err();
+        assert_eq!(error.to_string(), "Expected bytes, found fixstr");
+        assert_eq!(::from_bytes::<&str>(&[0xc4, 0x02, 0x68, 0x69]).unwrap(), "hi");
+    }
+
+    #[test]
+    fn strict_lenient_test() {
+        // whichever is turned on last wins
+        let config = ::DeserializerConfig::new().strict(true).lenient(true);
+        assert_eq!(::from_bytes_with_config::<&str>(&[0xc4, 0x01, 0x61], config).unwrap(), "a");
+
+        let config = ::DeserializerConfig::new().lenient(true).strict(true);
+        assert!(::from_bytes_with_config::<&str>(&[0xc4, 0x01, 0x61], config).is_err());
+        assert!(::from_bytes_with_config::<u8>(&[0xa1, 0x31], config).is_err());
+
+        // turning one off leaves the other alone
+        let config = ::DeserializerConfig::new().strict(true).lenient(false);
+        assert!(::from_bytes_with_config::<&str>(&[0xc4, 0x01, 0x61], config).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Small {
+        A,
+        B,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Open {
+        A,
+        B(u8),
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[test]
+    fn variant_out_of_range_test() {
+        assert_eq!(::from_bytes::<Small>(&[0x92, 0x01, 0xc0]).unwrap(), Small::B);
+        assert!(::from_bytes::<Small>(&[0x92, 0x02, 0xc0]).is_err());
+        assert!(strict::<Small>(&[0x92, 0x05, 0xc0]).is_err());
+
+        // [true, nil]
+        match strict::<Small>(&[0x92, 0xc3, 0xc0]) {
+            Err(Error::TypeMismatch { found: "bool", expected: "variant index" }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_variant_test() {
+        assert_eq!(::from_bytes::<Open>(&[0x92, 0x01, 0x07]).unwrap(), Open::B(7));
+        assert_eq!(::from_bytes::<Open>(&[0x92, 0x05, 0xc0]).unwrap(), Open::Unknown);
+
+        // a newer variant with a payload, followed by more data
+        let bytes = &[0x92, 0x92, 0x09, 0x92, 0x01, 0xa1, 0x78, 0x92, 0x01, 0x02];
+        assert_eq!(::from_bytes::<Vec<Open>>(bytes).unwrap(), vec![Open::Unknown, Open::B(2)]);
+
+        assert!(strict::<Open>(&[0x92, 0x05, 0xc0]).is_err());
+    }
+
+    #[test]
+    fn duplicate_key_test() {
+        let config = ::DeserializerConfig::new().reject_duplicate_keys(true);
+
+        // {"a": 1, "a": 2}
+        let bytes = &[0x82, 0xa1, 0x61, 0x01, 0xa1, 0x61, 0x02];
+        assert_eq!(::from_bytes::<BTreeMap<String, u8>>(bytes).unwrap().get("a"), Some(&2));
+
+        match ::from_bytes_with_config::<BTreeMap<String, u8>>(bytes, config) {
+            Err(Error::DuplicateKey) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // an ignored field that appears twice
+        let bytes = &[0x83, 0xa1, 0x61, 0x01, 0xa1, 0x62, 0x02, 0xa1, 0x62, 0x03];
+        assert_eq!(::from_bytes::<Known>(bytes).unwrap().a, 1);
+        assert!(::from_bytes_with_config::<Known>(bytes, config).is_err());
+
+        // the same key in different maps is fine, and so are nested maps as keys
+        let bytes = ::to_bytes(vec![(1, 2), (1, 3)].into_iter()
+                .map(|entry| vec![entry].into_iter().collect::<BTreeMap<_, _>>())
+                .collect::<Vec<_>>())
+            .unwrap();
+        assert!(::from_bytes_with_config::<Vec<BTreeMap<u8, u8>>>(&bytes, config).is_ok());
+    }
+
+    #[test]
+    fn duplicate_key_from_iter_test() {
+        use serde::Deserialize;
+
+        let config = ::DeserializerConfig::new().reject_duplicate_keys(true);
+
+        // {[1]: 1, [1]: 2}
+        let bytes = [0x82, 0x91, 0x01, 0x01, 0x91, 0x01, 0x02];
+        let mut iter = bytes.iter().cloned();
+        let mut de = Deserializer::with_config(::read::CopyRead::new(|buf: &mut [u8]| {
+                                                   for byte in buf.iter_mut() {
+                                                       *byte = iter.next()
+                                                           .ok_or(Error::EndOfStream)?;
+                                                   }
+                                                   Ok(())
+                                               }),
+                                               config);
+
+        match BTreeMap::<Vec<u8>, u8>::deserialize(&mut de) {
+            Err(Error::DuplicateKey) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn utf8_policy_test() {
+        use super::Utf8Policy;
+
+        // "caf\xe9" in Latin-1
+        let bytes = &[0xa4, 0x63, 0x61, 0x66, 0xe9];
+        let config = ::DeserializerConfig::new();
+
+        match ::from_bytes::<String>(bytes) {
+            Err(Error::Utf8Error(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let lossy = config.utf8_policy(Utf8Policy::Lossy);
+        assert_eq!(::from_bytes_with_config::<String>(bytes, lossy).unwrap(), "caf\u{fffd}");
+        assert!(::from_iter::<_, String>(bytes.iter().cloned()).is_err());
+
+        // a borrowed str can't hold the replacement
+        assert!(::from_bytes_with_config::<&str>(bytes, lossy).is_err());
+
+        let as_bytes = config.utf8_policy(Utf8Policy::AsBytes);
+        assert_eq!(::from_bytes_with_config::<&[u8]>(bytes, as_bytes).unwrap(),
+                   &[0x63, 0x61, 0x66, 0xe9]);
+        assert!(::from_bytes_with_config::<String>(bytes, as_bytes).is_err());
+
+        // valid strings are unaffected
+        assert_eq!(::from_bytes_with_config::<&str>(&[0xa1, 0x61], lossy).unwrap(), "a");
+
+        // lenient conversions from text follow the same policy: "1\xe9"
+        let bytes = &[0xa2, 0x31, 0xe9];
+        let lenient = config.lenient(true);
+
+        match ::from_bytes_with_config::<u8>(bytes, lenient) {
+            Err(Error::Utf8Error(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        for &utf8 in &[Utf8Policy::Lossy, Utf8Policy::AsBytes] {
+            match ::from_bytes_with_config::<u8>(bytes, lenient.utf8_policy(utf8)) {
+                Err(Error::BadType) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+
+        let lossy = lenient.utf8_policy(Utf8Policy::Lossy);
+        assert_eq!(::from_bytes_with_config::<u8>(&[0xa1, 0x31], lossy).unwrap(), 1);
+    }
+
+    #[test]
+    fn str32_test() {
+        let mut bytes = vec![0xdb, 0x00, 0x01, 0x00, 0x00];
+        bytes.extend_from_slice(&[0x61; 0x10000]);
+
+        assert_eq!(::from_bytes::<String>(&bytes).unwrap().len(), 0x10000);
+    }
+
+    // reads the first two elements of a sequence and stops
+    #[derive(Debug, PartialEq)]
+    struct FirstTwo(u8, u8);
+
+    impl<'de> ::serde::Deserialize<'de> for FirstTwo {
+        fn deserialize<D>(deserializer: D) -> Result<FirstTwo, D::Error>
+            where D: ::serde::Deserializer<'de>
+        {
+            struct Visitor;
+
+            impl<'de> ::serde::de::Visitor<'de> for Visitor {
+                type Value = FirstTwo;
+
+                fn expecting(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    fmt.write_str("a sequence")
+                }
+
+                fn visit_seq<A>(self, mut seq: A) -> Result<FirstTwo, A::Error>
+                    where A: ::serde::de::SeqAccess<'de>
+                {
+                    let first = seq.next_element()?.unwrap_or(0);
+                    let second = seq.next_element()?.unwrap_or(0);
+                    Ok(FirstTwo(first, second))
+                }
+            }
+
+            deserializer.deserialize_seq(Visitor)
+        }
+    }
+
+    #[test]
+    fn leftover_elements_test() {
+        assert_eq!(::from_bytes::<Vec<FirstTwo>>(&[0x91, 0x92, 0x01, 0x02]).unwrap(),
+                   vec![FirstTwo(1, 2)]);
+
+        // the third element would otherwise be read as the next FirstTwo
+        match ::from_bytes::<Vec<FirstTwo>>(&[0x92, 0x93, 0x01, 0x02, 0x03, 0x92, 0x04, 0x05]) {
+            Err(Error::BadLength) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tuple_length_test() {
+        assert_eq!(::from_bytes::<(u8, u8)>(&[0x92, 0x01, 0x02]).unwrap(), (1, 2));
+        assert!(::from_bytes::<(u8, u8)>(&[0x93, 0x01, 0x02, 0x03]).is_err());
+        assert!(::from_bytes::<(u8, u8, u8)>(&[0x92, 0x01, 0x02]).is_err());
+        assert!(::from_bytes::<[u8; 2]>(&[0x93, 0x01, 0x02, 0x03]).is_err());
+        assert!(::from_bytes::<[u8; 2]>(&[0xc4, 0x03, 0x01, 0x02, 0x03]).is_err());
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(u8),
+        Rect { w: u8, h: u8 },
+    }
+
+    #[test]
+    fn enum_forms_test() {
+        // [index, value], [name, value] and {name: value}
+        assert_eq!(::from_bytes::<Shape>(&[0x92, 0x01, 0x05]).unwrap(), Shape::Circle(5));
+        assert_eq!(::from_bytes::<Shape>(&[0x92, 0xa6, 0x43, 0x69, 0x72, 0x63, 0x6c, 0x65, 0x05])
+                       .unwrap(),
+                   Shape::Circle(5));
+        assert_eq!(::from_bytes::<Shape>(&[0x81, 0xa4, 0x52, 0x65, 0x63, 0x74, 0x82, 0xa1, 0x77,
+                                           0x02, 0xa1, 0x68, 0x03])
+                       .unwrap(),
+                   Shape::Rect { w: 2, h: 3 });
+
+        // bare unit variants
+        assert_eq!(::from_bytes::<Shape>(&[0x00]).unwrap(), Shape::Empty);
+        assert_eq!(::from_bytes::<Shape>(&[0xa5, 0x45, 0x6d, 0x70, 0x74, 0x79]).unwrap(),
+                   Shape::Empty);
+
+        // a bare variant that needs a value, and unknown names
+        assert!(::from_bytes::<Shape>(&[0x01]).is_err());
+        assert!(::from_bytes::<Shape>(&[0xa4, 0x4e, 0x6f, 0x6e, 0x65]).is_err());
+        assert!(::from_bytes::<Shape>(&[0x93, 0x01, 0x05, 0x06]).is_err());
+    }
+
+    #[test]
+    fn unknown_variant_name_test() {
+        assert_eq!(::from_bytes::<Open>(&[0x81, 0xa1, 0x5a, 0x07]).unwrap(), Open::Unknown);
+        assert_eq!(::from_bytes::<Open>(&[0xa1, 0x5a]).unwrap(), Open::Unknown);
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Version1 {
+        id: u8,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Version2 {
+        id: u8,
+        name: String,
+    }
+
+    // {"id": 5}, then 7
+    const VERSIONS: &[u8] = &[0x81, 0xa2, 0x69, 0x64, 0x05, 0x07];
+
+    fn copy_read<'a>(bytes: &'a [u8])
+                     -> ::read::CopyRead<impl FnMut(&mut [u8]) -> Result<(), Error> + 'a> {
+        let mut iter = bytes.iter().cloned();
+        ::read::CopyRead::new(move |buf: &mut [u8]| {
+            for byte in buf.iter_mut() {
+                *byte = iter.next().ok_or(Error::EndOfStream)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn speculate<'de, R: ::read::Read<'de>>(de: &mut Deserializer<'de, R>) {
+        use serde::Deserialize;
+
+        let checkpoint = de.checkpoint();
+        assert!(Version2::deserialize(&mut *de).is_err());
+        de.rewind(checkpoint).unwrap();
+
+        assert_eq!(Version1::deserialize(&mut *de).unwrap(), Version1 { id: 5 });
+        assert_eq!(u8::deserialize(&mut *de).unwrap(), 7);
+    }
+
+    #[test]
+    fn rewind_slice_test() {
+        speculate(&mut Deserializer::new(::read::SliceRead::new(VERSIONS)));
+    }
+
+    #[test]
+    fn rewind_copy_test() {
+        speculate(&mut Deserializer::new(copy_read(VERSIONS)));
+    }
+
+    // hands out exactly as much of bytes as is asked for
+    fn borrow_read<'a>(mut bytes: &'a [u8])
+                       -> ::read::BorrowRead<'a, impl FnMut(usize) -> Result<&'a [u8], Error>> {
+        ::read::BorrowRead::new(move |len| {
+            if len > bytes.len() {
+                return Err(Error::EndOfStream);
+            }
+
+            let (data, rest) = bytes.split_at(len);
+            bytes = rest;
+            Ok(data)
+        })
+    }
+
+    #[test]
+    fn borrow_peek_test() {
+        use serde::Deserialize;
+
+        // [true, "abc"]
+        let bytes = [0x92, 0xc3, 0xa3, 0x61, 0x62, 0x63];
+        let mut calls = 0;
+        let mut rest = &bytes[..];
+
+        let value = {
+            // hand out everything at once, so peeking and reading just index into it
+            let mut de = Deserializer::new(::read::BorrowRead::new(|_| {
+                calls += 1;
+                Ok(::std::mem::take(&mut rest))
+            }));
+
+            Option::<&str>::deserialize(&mut de).unwrap()
+        };
+
+        assert_eq!(value, Some("abc"));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn rewind_borrow_test() {
+        use serde::Deserialize;
+
+        speculate(&mut Deserializer::new(borrow_read(VERSIONS)));
+
+        // "abc", then bin [1, 2]
+        let mut de = Deserializer::new(borrow_read(&[0xa3, 0x61, 0x62, 0x63, 0xc4, 0x02, 0x01,
+                                                     0x02]));
+
+        let checkpoint = de.checkpoint();
+        assert_eq!(<&str>::deserialize(&mut de).unwrap(), "abc");
+        assert_eq!(<&[u8]>::deserialize(&mut de).unwrap(), &[1, 2]);
+        de.rewind(checkpoint).unwrap();
+
+        // replayed data is still borrowed from the input
+        assert_eq!(<&str>::deserialize(&mut de).unwrap(), "abc");
+        assert_eq!(<&[u8]>::deserialize(&mut de).unwrap(), &[1, 2]);
+    }
+
+    #[test]
+    fn nested_checkpoint_test() {
+        use serde::Deserialize;
+
+        // [1, 2, 3]
+        let mut de = Deserializer::new(copy_read(&[0x01, 0x02, 0x03]));
+
+        let outer = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 1);
+
+        let inner = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 2);
+        de.rewind(inner).unwrap();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 2);
+
+        de.rewind(outer).unwrap();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 1);
+
+        // released checkpoints can't be rewound to
+        let checkpoint = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 2);
+        de.release(checkpoint);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 3);
+    }
+
+    #[test]
+    fn nested_replay_test() {
+        use serde::Deserialize;
+
+        // [1, 2, 3, 4, 5]
+        let mut de = Deserializer::new(copy_read(&[0x01, 0x02, 0x03, 0x04, 0x05]));
+
+        let outer = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 1);
+
+        let inner = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 2);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 3);
+        de.rewind(inner).unwrap();
+
+        // the outer checkpoint is still active while the inner one's data is replayed
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 2);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 3);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 4);
+        de.rewind(outer).unwrap();
+
+        for i in 1..6 {
+            assert_eq!(u8::deserialize(&mut de).unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn checkpoint_while_replaying_test() {
+        use serde::Deserialize;
+
+        // [1, 2, 3, 4, 5]
+        let mut de = Deserializer::new(copy_read(&[0x01, 0x02, 0x03, 0x04, 0x05]));
+
+        let checkpoint = de.checkpoint();
+        for i in 1..5 {
+            assert_eq!(u8::deserialize(&mut de).unwrap(), i);
+        }
+        de.rewind(checkpoint).unwrap();
+
+        // a checkpoint taken in the middle of the replayed data
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 1);
+        let checkpoint = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 2);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 3);
+        de.rewind(checkpoint).unwrap();
+
+        for i in 2..6 {
+            assert_eq!(u8::deserialize(&mut de).unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn bare_option_rewind_test() {
+        use serde::Deserialize;
+
+        // [[5, 6], 7], [[0 x 40], [5, 6]]
+        let mut bytes = vec![0x92, 0x92, 0x05, 0x06, 0x07, 0x92, 0xdc, 0x00, 0x28];
+        bytes.extend_from_slice(&[0; 40]);
+        bytes.extend_from_slice(&[0x92, 0x05, 0x06]);
+
+        let config = ::DeserializerConfig::new().bare_options(true);
+        let mut de = Deserializer::with_config(copy_read(&bytes).replay_limit(8), config);
+
+        let checkpoint = de.checkpoint();
+        assert_eq!(<(Option<Vec<u8>>, u8)>::deserialize(&mut de).unwrap(),
+                   (Some(vec![5, 6]), 7));
+        de.rewind(checkpoint).unwrap();
+        assert_eq!(<(Option<Vec<u8>>, u8)>::deserialize(&mut de).unwrap(),
+                   (Some(vec![5, 6]), 7));
+
+        // options don't need the replay buffer, even once a checkpoint has overflowed it
+        let checkpoint = de.checkpoint();
+        assert_eq!(<(Vec<u8>, Option<Vec<u8>>)>::deserialize(&mut de).unwrap(),
+                   (vec![0; 40], Some(vec![5, 6])));
+        de.release(checkpoint);
+    }
+
+    #[test]
+    fn replay_limit_test() {
+        use serde::Deserialize;
+
+        let mut de = Deserializer::new(copy_read(VERSIONS).replay_limit(4));
+
+        let checkpoint = de.checkpoint();
+        assert_eq!(Version1::deserialize(&mut de).unwrap(), Version1 { id: 5 });
+
+        match de.rewind(checkpoint) {
+            Err(Error::BadCheckpoint) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 7);
+    }
+
+    #[test]
+    fn rewind_after_overflow_test() {
+        use serde::Deserialize;
+
+        // [1, 2, 3, 4, 5, 6]
+        let mut de = Deserializer::new(copy_read(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06])
+            .replay_limit(2));
+
+        let checkpoint = de.checkpoint();
+        for i in 1..5 {
+            assert_eq!(u8::deserialize(&mut de).unwrap(), i);
+        }
+        assert!(de.rewind(checkpoint).is_err());
+
+        // the failed rewind dropped the checkpoint, so new ones work again
+        let checkpoint = de.checkpoint();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 5);
+        de.rewind(checkpoint).unwrap();
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 5);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 6);
+    }
+
+    #[test]
+    fn reset_test() {
+        use serde::Deserialize;
+
+        let first = [0xa3, 0x6f, 0x6e, 0x65];
+        let second = [0xa3, 0x74, 0x77, 0x6f, 0x07];
+
+        let mut de = Deserializer::new(::read::SliceRead::new(&first));
+        assert_eq!(<&str>::deserialize(&mut de).unwrap(), "one");
+
+        let old = de.reset(::read::SliceRead::new(&second));
+        assert_eq!(old.position(), 4);
+        assert_eq!(<&str>::deserialize(&mut de).unwrap(), "two");
+        assert_eq!(de.into_inner().position(), 4);
+    }
+
+    #[test]
+    fn scratch_test() {
+        use serde::Deserialize;
+
+        let mut scratch = Vec::with_capacity(256);
+        let buffer = scratch.as_ptr();
+
+        for message in &[&[0xa3, 0x6f, 0x6e, 0x65][..], &[0xa3, 0x74, 0x77, 0x6f][..]] {
+            let mut de = Deserializer::new(copy_read(message)).with_scratch(scratch);
+            assert_eq!(String::deserialize(&mut de).unwrap().len(), 3);
+            scratch = de.take_scratch();
+        }
+
+        assert_eq!(scratch.as_ptr(), buffer);
+        assert!(scratch.capacity() >= 256);
+    }
+
+    #[test]
+    fn capture_scratch_test() {
+        use serde::Deserialize;
+
+        let mut de = Deserializer::new(copy_read(&[])).with_scratch(Vec::with_capacity(256));
+        let buffer = de.scratch.as_ptr();
+
+        // captured values are copied out to scratch, which keeps its allocation
+        for message in &[&[0x92, 0x01, 0xa1, 0x61][..], &[0x91, 0xa3, 0x74, 0x77, 0x6f][..]] {
+            de.reset(copy_read(message));
+            let raw = ::raw::Raw::deserialize(&mut de).unwrap();
+            assert_eq!(raw.as_bytes(), *message);
+        }
+
+        let scratch = de.take_scratch();
+        assert_eq!(scratch.as_ptr(), buffer);
+        assert!(scratch.capacity() >= 256);
+    }
 }
@@ -100,16 +100,66 @@ pub const NEG_FIXINT: InclusiveRange<u8> = InclusiveRange {
     end: 0xff,
 };
 
+// the quiet NaNs written in canonical mode
+pub const CANONICAL_NAN32: u32 = 0x7fc0_0000;
+pub const CANONICAL_NAN64: u64 = 0x7ff8_0000_0000_0000;
+
+// newtype name used to pass pre-encoded values through serde
+pub const RAW_TOKEN: &str = "$corepack::Raw";
+
 // bit masks
 pub const FIXMAP_MASK: u8 = 0b1000_0000;
 pub const FIXARRAY_MASK: u8 = 0b1001_0000;
 pub const FIXSTR_MASK: u8 = 0b1010_0000;
 
 // type sizes
+pub const U128_BYTES: usize = 16;
 pub const U64_BYTES: usize = 8;
 pub const U32_BYTES: usize = 4;
 pub const U16_BYTES: usize = 2;
 
+// the name of the type a marker starts, for error messages
+pub fn marker_name(ty: u8) -> &'static str {
+    match ty {
+        v if POS_FIXINT.contains(v) => "positive fixint",
+        v if FIXMAP.contains(v) => "fixmap",
+        v if FIXARRAY.contains(v) => "fixarray",
+        v if FIXSTR.contains(v) => "fixstr",
+        v if NEG_FIXINT.contains(v) => "negative fixint",
+        NIL => "nil",
+        FALSE | TRUE => "bool",
+        BIN8 => "bin8",
+        BIN16 => "bin16",
+        BIN32 => "bin32",
+        EXT8 => "ext8",
+        EXT16 => "ext16",
+        EXT32 => "ext32",
+        FLOAT32 => "float32",
+        FLOAT64 => "float64",
+        UINT8 => "uint8",
+        UINT16 => "uint16",
+        UINT32 => "uint32",
+        UINT64 => "uint64",
+        INT8 => "int8",
+        INT16 => "int16",
+        INT32 => "int32",
+        INT64 => "int64",
+        FIXEXT1 => "fixext1",
+        FIXEXT2 => "fixext2",
+        FIXEXT4 => "fixext4",
+        FIXEXT8 => "fixext8",
+        FIXEXT16 => "fixext16",
+        STR8 => "str8",
+        STR16 => "str16",
+        STR32 => "str32",
+        ARRAY16 => "array16",
+        ARRAY32 => "array32",
+        MAP16 => "map16",
+        MAP32 => "map32",
+        _ => "reserved",
+    }
+}
+
 pub fn read_signed(unsigned: u8) -> i8 {
     LittleEndian::read_i16(&[unsigned, 0]) as i8
 }
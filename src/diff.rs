@@ -0,0 +1,640 @@
+//! Structural differences between encoded documents.
+//!
+//! `diff` compares two documents map key by map key and array position by array position, and
+//! produces a `Patch` that `apply` can replay against the old document. The patch only carries
+//! the parts of the new document that changed, and can itself be encoded with corepack.
+//!
+//! Containers that differ are compared down to `MAX_DEPTH` levels of nesting. Deeper changes
+//! fail with `Error::TooDeep`, rather than recursing without bound on untrusted input.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+
+#[cfg(not(feature = "alloc"))]
+use std::collections::BTreeMap;
+
+use std::fmt;
+
+use byteorder::{ByteOrder, BigEndian};
+
+use serde::de::{SeqAccess, Unexpected};
+use serde::{Serialize, Deserialize};
+
+use serde;
+
+use defs::*;
+use document::{Document, Container};
+use error::Error;
+use raw::{Raw, Encoded};
+
+/// How deeply nested a changed container can be before `diff` gives up.
+pub const MAX_DEPTH: usize = 128;
+
+/// A list of changes that turns one document into another.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+    ops: Vec<Op>,
+}
+
+/// A single change within a `Patch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Replace the value at path. If path names a missing map key, the entry is added.
+    Set { path: Vec<Step>, value: Raw },
+
+    /// Remove the map entry at path.
+    Remove { path: Vec<Step> },
+
+    /// Append a value to the array at path.
+    Push { path: Vec<Step>, value: Raw },
+
+    /// Shorten the array at path to len elements.
+    Truncate { path: Vec<Step>, len: usize },
+}
+
+/// One step of a path through a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// A map entry, identified by the encoded bytes of its key.
+    Key(Raw),
+
+    /// An array element.
+    Index(usize),
+}
+
+// where a container sits in the encoded data
+struct Location {
+    start: usize,
+    end: usize,
+    kind: Option<Container>,
+    count: usize,
+}
+
+impl Patch {
+    /// The changes in this patch, in the order they are applied.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// True if the documents were identical.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Compute the changes that turn old into new.
+pub fn diff(old: &[u8], new: &[u8]) -> Result<Patch, Error> {
+    let mut ops = vec![];
+
+    diff_value(&mut vec![], Document::new(old), Document::new(new), &mut ops)?;
+
+    Ok(Patch { ops })
+}
+
+/// Apply a patch to a document, returning the patched document.
+pub fn apply(doc: &[u8], patch: &Patch) -> Result<Vec<u8>, Error> {
+    let mut bytes = Document::new(doc).encoded()?.to_vec();
+
+    for op in &patch.ops {
+        apply_op(&mut bytes, op)?;
+    }
+
+    Ok(bytes)
+}
+
+fn diff_value(path: &mut Vec<Step>,
+              old: Document,
+              new: Document,
+              ops: &mut Vec<Op>)
+              -> Result<(), Error> {
+    let new_bytes = new.encoded()?;
+
+    if old.encoded()? == new_bytes {
+        Ok(())
+    } else if (old.is_map() || old.is_array()) && path.len() >= MAX_DEPTH {
+        Err(Error::TooDeep)
+    } else if old.is_map() && new.is_map() {
+        diff_map(path, old, new, ops)
+    } else if old.is_array() && new.is_array() {
+        diff_array(path, old, new, ops)
+    } else {
+        ops.push(Op::Set {
+            path: path.clone(),
+            value: Raw::new(new_bytes.to_vec())?,
+        });
+
+        Ok(())
+    }
+}
+
+fn diff_map(path: &mut Vec<Step>,
+            old: Document,
+            new: Document,
+            ops: &mut Vec<Op>)
+            -> Result<(), Error> {
+    let mut added = BTreeMap::new();
+
+    for entry in new.entries()? {
+        let (key, value) = entry?;
+        added.insert(key.encoded()?, value);
+    }
+
+    for entry in old.entries()? {
+        let (key, value) = entry?;
+        let key = key.encoded()?;
+
+        path.push(Step::Key(Raw::new(key.to_vec())?));
+
+        match added.remove(key) {
+            Some(new_value) => diff_value(path, value, new_value, ops)?,
+            None => ops.push(Op::Remove { path: path.clone() }),
+        }
+
+        path.pop();
+    }
+
+    // whatever is left only exists in the new map
+    for (key, value) in added {
+        let mut entry_path = path.clone();
+        entry_path.push(Step::Key(Raw::new(key.to_vec())?));
+
+        ops.push(Op::Set {
+            path: entry_path,
+            value: Raw::new(value.encoded()?.to_vec())?,
+        });
+    }
+
+    Ok(())
+}
+
+fn diff_array(path: &mut Vec<Step>,
+              old: Document,
+              new: Document,
+              ops: &mut Vec<Op>)
+              -> Result<(), Error> {
+    let mut old_elements = old.elements()?;
+    let mut new_elements = new.elements()?;
+    let mut index = 0;
+
+    loop {
+        match (old_elements.next(), new_elements.next()) {
+            (Some(old_value), Some(new_value)) => {
+                path.push(Step::Index(index));
+                diff_value(path, old_value?, new_value?, ops)?;
+                path.pop();
+            }
+            (Some(_), None) => {
+                ops.push(Op::Truncate {
+                    path: path.clone(),
+                    len: index,
+                });
+
+                return Ok(());
+            }
+            (None, Some(new_value)) => {
+                ops.push(Op::Push {
+                    path: path.clone(),
+                    value: Raw::new(new_value?.encoded()?.to_vec())?,
+                });
+            }
+            (None, None) => return Ok(()),
+        }
+
+        index += 1;
+    }
+}
+
+fn apply_op(bytes: &mut Vec<u8>, op: &Op) -> Result<(), Error> {
+    match *op {
+        Op::Set { ref path, ref value } => {
+            if let Some(target) = locate(bytes, path)? {
+                bytes.splice(target.start..target.end, value.as_bytes().iter().cloned());
+                return Ok(());
+            }
+
+            // a missing key gets added to the end of its map
+            let (key, parent) = split_key(path)?;
+            let mut entry = key.as_bytes().to_vec();
+            entry.extend_from_slice(value.as_bytes());
+
+            append(bytes, parent, Container::Map, &entry)
+        }
+        Op::Remove { ref path } => {
+            let (key, parent) = split_key(path)?;
+            let map = expect(locate(bytes, parent)?, Container::Map)?;
+            let entry = find_entry(bytes, map.start, key)?.ok_or(Error::BadPath)?;
+
+            bytes.drain(entry.0..entry.1);
+            set_count(bytes, &map, map.count - 1)
+        }
+        Op::Push { ref path, ref value } => append(bytes, path, Container::Array, value.as_bytes()),
+        Op::Truncate { ref path, len } => {
+            let array = expect(locate(bytes, path)?, Container::Array)?;
+
+            if len > array.count {
+                return Err(Error::BadLength);
+            }
+
+            let cut = match Document::new(&bytes[array.start..]).index(len)? {
+                Some(element) => bytes.len() - element.tail().len(),
+                None => array.end,
+            };
+
+            bytes.drain(cut..array.end);
+            set_count(bytes, &array, len)
+        }
+    }
+}
+
+fn split_key(path: &[Step]) -> Result<(&Raw, &[Step]), Error> {
+    match path.split_last() {
+        Some((last, parent)) => {
+            match *last {
+                Step::Key(ref key) => Ok((key, parent)),
+                Step::Index(_) => Err(Error::BadPath),
+            }
+        }
+        None => Err(Error::BadPath),
+    }
+}
+
+fn expect(location: Option<Location>, kind: Container) -> Result<Location, Error> {
+    match location {
+        Some(location) => {
+            if location.kind == Some(kind) {
+                Ok(location)
+            } else {
+                Err(Error::BadType)
+            }
+        }
+        None => Err(Error::BadPath),
+    }
+}
+
+fn append(bytes: &mut Vec<u8>, path: &[Step], kind: Container, data: &[u8]) -> Result<(), Error> {
+    let container = expect(locate(bytes, path)?, kind)?;
+
+    bytes.splice(container.end..container.end, data.iter().cloned());
+    set_count(bytes, &container, container.count + 1)
+}
+
+// finds the range of the map entry with the given key
+fn find_entry(bytes: &[u8], start: usize, key: &Raw) -> Result<Option<(usize, usize)>, Error> {
+    for entry in Document::new(&bytes[start..]).entries()? {
+        let (k, v) = entry?;
+
+        if k.encoded()? == key.as_bytes() {
+            let entry_start = bytes.len() - k.tail().len();
+            let entry_end = bytes.len() - v.tail().len() + v.encoded()?.len();
+            return Ok(Some((entry_start, entry_end)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn locate(bytes: &[u8], path: &[Step]) -> Result<Option<Location>, Error> {
+    let mut doc = Document::new(bytes);
+
+    for step in path {
+        let child = match *step {
+            Step::Index(index) => doc.index(index)?,
+            Step::Key(ref key) => {
+                let mut found = None;
+
+                for entry in doc.entries()? {
+                    let (k, v) = entry?;
+
+                    if k.encoded()? == key.as_bytes() {
+                        found = Some(v);
+                        break;
+                    }
+                }
+
+                found
+            }
+        };
+
+        match child {
+            Some(child) => doc = child,
+            None => return Ok(None),
+        }
+    }
+
+    let start = bytes.len() - doc.tail().len();
+    let header = doc.container()?;
+
+    Ok(Some(Location {
+        start,
+        end: start + doc.encoded()?.len(),
+        kind: header.as_ref().map(|header| header.kind),
+        count: header.map(|header| header.count).unwrap_or(0),
+    }))
+}
+
+// rewrites the header of the container at location for a new item count
+fn set_count(bytes: &mut Vec<u8>, location: &Location, count: usize) -> Result<(), Error> {
+    let header_len = {
+        let doc = Document::new(&bytes[location.start..]);
+        let rest = doc.container()?.ok_or(Error::BadType)?.rest;
+        doc.tail().len() - rest.len()
+    };
+
+    let mut header = vec![];
+    match (location.kind, count) {
+        (Some(Container::Array), count) if count <= MAX_FIXARRAY => {
+            header.push(count as u8 | FIXARRAY_MASK)
+        }
+        (Some(Container::Map), count) if count <= MAX_FIXMAP => {
+            header.push(count as u8 | FIXMAP_MASK)
+        }
+        (Some(kind), count) if count <= MAX_ARRAY16 => {
+            header.push(if kind == Container::Array { ARRAY16 } else { MAP16 });
+            header.extend_from_slice(&[0; U16_BYTES]);
+            BigEndian::write_u16(&mut header[1..], count as u16);
+        }
+        (Some(kind), count) if count <= MAX_ARRAY32 => {
+            header.push(if kind == Container::Array { ARRAY32 } else { MAP32 });
+            header.extend_from_slice(&[0; U32_BYTES]);
+            BigEndian::write_u32(&mut header[1..], count as u32);
+        }
+        (Some(_), _) => return Err(Error::TooBig),
+        (None, _) => return Err(Error::BadType),
+    }
+
+    bytes.splice(location.start..location.start + header_len, header);
+    Ok(())
+}
+
+// Patches are encoded as an array of ops. Each op is an array holding a tag, the path, and
+// then the value or length if the op has one. Index steps are integers, and key steps are bin
+// values holding the encoded key.
+const SET: u8 = 0;
+const REMOVE: u8 = 1;
+const PUSH: u8 = 2;
+const TRUNCATE: u8 = 3;
+
+struct OpVisitor;
+
+struct StepVisitor;
+
+impl Serialize for Patch {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        self.ops.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Patch {
+    fn deserialize<D>(deserializer: D) -> Result<Patch, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        Ok(Patch { ops: Vec::deserialize(deserializer)? })
+    }
+}
+
+impl Serialize for Op {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        match *self {
+            Op::Set { ref path, ref value } => (SET, path, value).serialize(serializer),
+            Op::Remove { ref path } => (REMOVE, path).serialize(serializer),
+            Op::Push { ref path, ref value } => (PUSH, path, value).serialize(serializer),
+            Op::Truncate { ref path, len } => (TRUNCATE, path, len as u64).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Op {
+    fn deserialize<D>(deserializer: D) -> Result<Op, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        deserializer.deserialize_seq(OpVisitor)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for OpVisitor {
+    type Value = Op;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a patch operation")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Op, A::Error>
+        where A: SeqAccess<'de>
+    {
+        use serde::de::Error;
+
+        let tag: u8 = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let path = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(1, &self))?;
+
+        if tag == REMOVE {
+            return Ok(Op::Remove { path });
+        }
+
+        match tag {
+            SET | PUSH => {
+                let value = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(2, &self))?;
+
+                Ok(if tag == SET {
+                    Op::Set { path, value }
+                } else {
+                    Op::Push { path, value }
+                })
+            }
+            TRUNCATE => {
+                let len: u64 = seq.next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+
+                Ok(Op::Truncate {
+                    path,
+                    len: len as usize,
+                })
+            }
+            _ => Err(A::Error::invalid_value(Unexpected::Unsigned(tag as u64), &self)),
+        }
+    }
+}
+
+impl Serialize for Step {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        match *self {
+            Step::Key(ref key) => Encoded(key.as_bytes()).serialize(serializer),
+            Step::Index(index) => serializer.serialize_u64(index as u64),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D>(deserializer: D) -> Result<Step, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        deserializer.deserialize_any(StepVisitor)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for StepVisitor {
+    type Value = Step;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("an array index or an encoded map key")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Step, E>
+        where E: serde::de::Error
+    {
+        Ok(Step::Index(v as usize))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Step, E>
+        where E: serde::de::Error
+    {
+        Raw::new(v.to_vec()).map(Step::Key).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use error::Error;
+
+    use super::{diff, apply, Op, Patch, Step, MAX_DEPTH};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Node {
+        version: u32,
+        name: String,
+        peers: Vec<String>,
+        labels: BTreeMap<String, u32>,
+    }
+
+    fn node() -> Node {
+        let mut labels = BTreeMap::new();
+        labels.insert("a".into(), 1);
+        labels.insert("b".into(), 2);
+
+        Node {
+            version: 1,
+            name: "alpha".into(),
+            peers: vec!["beta".into(), "gamma".into()],
+            labels,
+        }
+    }
+
+    fn round_trip(old: &Node, new: &Node) -> Patch {
+        let old_bytes = ::to_bytes(old).unwrap();
+        let new_bytes = ::to_bytes(new).unwrap();
+
+        let patch = diff(&old_bytes, &new_bytes).unwrap();
+
+        // patches survive being sent over the wire
+        let patch: Patch = ::from_bytes(&::to_bytes(&patch).unwrap()).unwrap();
+
+        let patched = apply(&old_bytes, &patch).unwrap();
+        assert_eq!(&::from_bytes::<Node>(&patched).unwrap(), new);
+
+        patch
+    }
+
+    #[test]
+    fn identical_test() {
+        assert!(round_trip(&node(), &node()).is_empty());
+    }
+
+    #[test]
+    fn scalar_test() {
+        let mut new = node();
+        new.version = 2;
+
+        let patch = round_trip(&node(), &new);
+        assert_eq!(patch.ops().len(), 1);
+
+        match patch.ops()[0] {
+            Op::Set { ref path, ref value } => {
+                assert_eq!(path, &vec![Step::Key(::Raw::from_value("version").unwrap())]);
+                assert_eq!(value.as_bytes(), &[0x02]);
+            }
+            ref other => panic!("unexpected op {:?}", other),
+        }
+    }
+
+    #[test]
+    fn array_test() {
+        let mut longer = node();
+        longer.peers.push("delta".into());
+        longer.peers[0] = "epsilon".into();
+        round_trip(&node(), &longer);
+
+        let mut shorter = node();
+        shorter.peers.truncate(0);
+        let patch = round_trip(&node(), &shorter);
+        assert_eq!(patch.ops().len(), 1);
+
+        let mut many = node();
+        many.peers = (0..20).map(|i| format!("peer{}", i)).collect();
+        round_trip(&node(), &many);
+        round_trip(&many, &node());
+    }
+
+    #[test]
+    fn map_test() {
+        let mut new = node();
+        new.labels.remove("a");
+        new.labels.insert("b".into(), 20);
+        new.labels.insert("c".into(), 3);
+        round_trip(&node(), &new);
+
+        let mut many = node();
+        for i in 0..20 {
+            many.labels.insert(format!("label{}", i), i);
+        }
+        round_trip(&node(), &many);
+        round_trip(&many, &node());
+    }
+
+    #[test]
+    fn root_test() {
+        let patch = diff(&::to_bytes(1).unwrap(), &::to_bytes("one").unwrap()).unwrap();
+        assert_eq!(apply(&::to_bytes(1).unwrap(), &patch).unwrap(),
+                   ::to_bytes("one").unwrap());
+    }
+
+    #[test]
+    fn deep_test() {
+        // differs only in the innermost value
+        let mut old = vec![0x91; 20_000];
+        let mut new = old.clone();
+        old.push(0x00);
+        new.push(0x01);
+
+        match diff(&old, &new) {
+            Err(Error::TooDeep) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // a change just inside the limit still works
+        let patch = diff(&old[old.len() - MAX_DEPTH - 1..], &new[new.len() - MAX_DEPTH - 1..])
+            .unwrap();
+        assert_eq!(patch.ops().len(), 1);
+    }
+
+    #[test]
+    fn bad_patch_test() {
+        let old = ::to_bytes(node()).unwrap();
+        let new = ::to_bytes(vec![1, 2, 3]).unwrap();
+        let patch = diff(&::to_bytes(vec![1]).unwrap(), &new).unwrap();
+
+        assert!(apply(&old, &patch).is_err());
+    }
+}
@@ -0,0 +1,398 @@
+//! A lazy view over encoded messagepack data.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+use byteorder::{ByteOrder, BigEndian};
+
+use serde::Deserialize;
+
+use de::value_len;
+use defs::*;
+use error::Error;
+
+/// A zero-copy view of one encoded value.
+///
+/// Nothing is decoded up front. Accessors decode only the part of the value they need, and
+/// navigation steps over sibling values without decoding them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Document<'a> {
+    // starts with the value, and may continue past it
+    bytes: &'a [u8],
+}
+
+/// Iterator over the elements of an array, returned by `Document::elements`.
+pub struct Elements<'a> {
+    rest: &'a [u8],
+    remaining: usize,
+}
+
+/// Iterator over the entries of a map, returned by `Document::entries`.
+pub struct Entries<'a> {
+    rest: &'a [u8],
+    remaining: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Container {
+    Array,
+    Map,
+}
+
+// an array or map header, with the data that follows it
+pub(crate) struct Header<'a> {
+    pub kind: Container,
+    pub count: usize,
+    pub rest: &'a [u8],
+}
+
+impl<'a> Document<'a> {
+    /// Create a view of the value at the start of bytes.
+    pub fn new(bytes: &'a [u8]) -> Document<'a> {
+        Document { bytes }
+    }
+
+    /// The exact encoded bytes of this value.
+    pub fn encoded(&self) -> Result<&'a [u8], Error> {
+        Ok(&self.bytes[..value_len(self.bytes)?])
+    }
+
+    /// Deserialize this value.
+    pub fn decode<V>(&self) -> Result<V, Error>
+        where V: Deserialize<'a>
+    {
+        ::from_bytes(self.bytes)
+    }
+
+    /// True if this is nil.
+    pub fn is_nil(&self) -> bool {
+        self.bytes.first() == Some(&NIL)
+    }
+
+    /// True if this is an array.
+    pub fn is_array(&self) -> bool {
+        self.kind() == Some(Container::Array)
+    }
+
+    /// True if this is a map.
+    pub fn is_map(&self) -> bool {
+        self.kind() == Some(Container::Map)
+    }
+
+    /// The value as a bool, or None if it is not a bool.
+    pub fn as_bool(&self) -> Option<bool> {
+        self.decode().ok()
+    }
+
+    /// The value as a u64, or None if it is not an integer that fits one.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.decode().ok()
+    }
+
+    /// The value as an i64, or None if it is not an integer that fits one.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.decode().ok()
+    }
+
+    /// The value as an f64, or None if it is not a float or an integer.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.decode().ok()
+    }
+
+    /// The value as a borrowed str, or None if it is not a valid UTF-8 str.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.bytes.first() {
+            Some(&v) if FIXSTR.contains(v) || v == STR8 || v == STR16 || v == STR32 => {
+                self.decode().ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// The value as borrowed bytes, or None if it is not bin.
+    pub fn as_bin(&self) -> Option<&'a [u8]> {
+        match self.bytes.first() {
+            Some(&BIN8) | Some(&BIN16) | Some(&BIN32) => self.decode().ok(),
+            _ => None,
+        }
+    }
+
+    /// The number of elements in an array or entries in a map.
+    pub fn len(&self) -> Option<usize> {
+        self.container().ok().and_then(|header| header).map(|header| header.count)
+    }
+
+    /// True if this is an empty array or map.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Look up the value for a string key, if this is a map.
+    pub fn get(&self, key: &str) -> Result<Option<Document<'a>>, Error> {
+        if !self.is_map() {
+            return Ok(None);
+        }
+
+        for entry in self.entries()? {
+            let (k, v) = entry?;
+
+            if k.as_str() == Some(key) {
+                return Ok(Some(v));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Look up an element by position, if this is an array.
+    pub fn index(&self, index: usize) -> Result<Option<Document<'a>>, Error> {
+        if !self.is_array() {
+            return Ok(None);
+        }
+
+        match self.elements()?.nth(index) {
+            Some(element) => element.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Iterate over the elements of an array. Any other value yields nothing.
+    pub fn elements(&self) -> Result<Elements<'a>, Error> {
+        Ok(match self.container()? {
+            Some(Header { kind: Container::Array, count, rest }) => {
+                Elements {
+                    rest,
+                    remaining: count,
+                }
+            }
+            _ => {
+                Elements {
+                    rest: &[],
+                    remaining: 0,
+                }
+            }
+        })
+    }
+
+    /// Iterate over the entries of a map. Any other value yields nothing.
+    pub fn entries(&self) -> Result<Entries<'a>, Error> {
+        Ok(match self.container()? {
+            Some(Header { kind: Container::Map, count, rest }) => {
+                Entries {
+                    rest,
+                    remaining: count,
+                }
+            }
+            _ => {
+                Entries {
+                    rest: &[],
+                    remaining: 0,
+                }
+            }
+        })
+    }
+
+    // the value and everything after it in the original input
+    pub(crate) fn tail(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    fn kind(&self) -> Option<Container> {
+        self.container().ok().and_then(|header| header).map(|header| header.kind)
+    }
+
+    // reads an array or map header
+    pub(crate) fn container(&self) -> Result<Option<Header<'a>>, Error> {
+        let ty = match self.bytes.first() {
+            Some(&ty) => ty,
+            None => return Err(Error::EndOfStream),
+        };
+
+        let (kind, count, header) = match ty {
+            v if FIXMAP.contains(v) => (Container::Map, (v & !FIXMAP_MASK) as usize, 1),
+            v if FIXARRAY.contains(v) => (Container::Array, (v & !FIXARRAY_MASK) as usize, 1),
+            ARRAY16 | MAP16 | ARRAY32 | MAP32 => {
+                let kind = if ty == ARRAY16 || ty == ARRAY32 {
+                    Container::Array
+                } else {
+                    Container::Map
+                };
+                let size = if ty == ARRAY16 || ty == MAP16 {
+                    U16_BYTES
+                } else {
+                    U32_BYTES
+                };
+
+                if self.bytes.len() < 1 + size {
+                    return Err(Error::EndOfStream);
+                }
+
+                let count = if size == U16_BYTES {
+                    BigEndian::read_u16(&self.bytes[1..]) as usize
+                } else {
+                    BigEndian::read_u32(&self.bytes[1..]) as usize
+                };
+
+                (kind, count, 1 + size)
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Header {
+            kind,
+            count,
+            rest: &self.bytes[header..],
+        }))
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Result<Document<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<Document<'a>, Error>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        match step(&mut self.rest) {
+            Ok(element) => Some(Ok(element)),
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<(Document<'a>, Document<'a>), Error>;
+
+    fn next(&mut self) -> Option<Result<(Document<'a>, Document<'a>), Error>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        match step(&mut self.rest).and_then(|key| step(&mut self.rest).map(|value| (key, value))) {
+            Ok(entry) => Some(Ok(entry)),
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+// takes the value at the start of rest, and advances rest past it
+fn step<'a>(rest: &mut &'a [u8]) -> Result<Document<'a>, Error> {
+    let bytes: &'a [u8] = rest;
+    let len = value_len(bytes)?;
+    *rest = &bytes[len..];
+    Ok(Document::new(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::Document;
+
+    #[derive(Serialize)]
+    struct Order {
+        id: u32,
+        price: f64,
+        tags: Vec<&'static str>,
+    }
+
+    fn fixture() -> Vec<u8> {
+        let mut map = BTreeMap::new();
+        map.insert("name", ::Raw::from_value("shop").unwrap());
+        map.insert("orders",
+                   ::Raw::from_value(&vec![Order {
+                                               id: 1,
+                                               price: 0.5,
+                                               tags: vec![],
+                                           },
+                                           Order {
+                                               id: 2,
+                                               price: 12.25,
+                                               tags: vec!["rush", "gift"],
+                                           }])
+                       .unwrap());
+        map.insert("blob", ::Raw::new(vec![0xc4, 0x02, 0xde, 0xad]).unwrap());
+        ::to_bytes(&map).unwrap()
+    }
+
+    #[test]
+    fn get_test() {
+        let bytes = fixture();
+        let doc = Document::new(&bytes);
+
+        assert!(doc.is_map());
+        assert_eq!(doc.len(), Some(3));
+        assert_eq!(doc.get("name").unwrap().and_then(|d| d.as_str()), Some("shop"));
+        assert_eq!(doc.get("blob").unwrap().and_then(|d| d.as_bin()),
+                   Some(&[0xde, 0xad][..]));
+        assert_eq!(doc.get("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn index_test() {
+        let bytes = fixture();
+        let orders = Document::new(&bytes).get("orders").unwrap().unwrap();
+
+        assert!(orders.is_array());
+        assert_eq!(orders.len(), Some(2));
+
+        let second = orders.index(1).unwrap().unwrap();
+        assert_eq!(second.get("id").unwrap().and_then(|d| d.as_u64()), Some(2));
+        assert_eq!(second.get("price").unwrap().and_then(|d| d.as_f64()), Some(12.25));
+
+        let tags = second.get("tags").unwrap().unwrap();
+        let tags: Vec<&str> = tags.elements()
+            .unwrap()
+            .map(|tag| tag.unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(tags, vec!["rush", "gift"]);
+
+        assert_eq!(orders.index(2).unwrap(), None);
+    }
+
+    #[test]
+    fn encoded_test() {
+        let bytes = fixture();
+        let blob = Document::new(&bytes).get("blob").unwrap().unwrap();
+
+        assert_eq!(blob.encoded().unwrap(), &[0xc4, 0x02, 0xde, 0xad]);
+    }
+
+    #[test]
+    fn mismatch_test() {
+        let doc = Document::new(&[0xa3, 0x61, 0x62, 0x63]);
+
+        assert_eq!(doc.as_u64(), None);
+        assert_eq!(doc.as_bin(), None);
+        assert_eq!(doc.len(), None);
+        assert_eq!(doc.get("a").unwrap(), None);
+        assert_eq!(doc.index(0).unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_test() {
+        let bytes = fixture();
+        let doc = Document::new(&bytes[..bytes.len() - 3]);
+
+        assert!(doc.get("orders").is_err());
+    }
+}
@@ -30,6 +30,27 @@ pub enum Error {
     /// Invalid length encountered.
     BadLength,
 
+    /// The same key appeared more than once in a map.
+    DuplicateKey,
+
+    /// Malformed path in a query.
+    BadPath,
+
+    /// Data was nested deeper than an operation supports.
+    TooDeep,
+
+    /// A checkpoint was already released, or too much data was read since it to rewind.
+    BadCheckpoint,
+
+    /// An encoded type was refused for the type it was deserialized as, in strict mode.
+    TypeMismatch {
+        /// The encoded type that was found.
+        found: &'static str,
+
+        /// The Rust type that was asked for.
+        expected: &'static str,
+    },
+
     /// Error decoding UTF8 string.
     Utf8Error(Utf8Error),
 
@@ -39,7 +60,12 @@ pub enum Error {
 
 impl Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_str(self.description())
+        match self {
+            &Error::TypeMismatch { found, expected } => {
+                write!(fmt, "Expected {}, found {}", expected, found)
+            }
+            _ => fmt.write_str(self.description()),
+        }
     }
 }
 
@@ -50,6 +76,11 @@ impl Error {
             &Error::EndOfStream => "End of stream",
             &Error::BadType => "Invalid type",
             &Error::BadLength => "Invalid length",
+            &Error::DuplicateKey => "Duplicate map key",
+            &Error::BadPath => "Invalid path",
+            &Error::TooDeep => "Nesting too deep",
+            &Error::BadCheckpoint => "Invalid checkpoint",
+            &Error::TypeMismatch { .. } => "Type mismatch",
             &Error::Utf8Error(_) => "UTF8 Error",
             &Error::Other(ref message) => &message,
         }
@@ -1,4 +1,24 @@
 //! corepack is a no_std support for messagepack in serde.
+//!
+//! # Tagged, untagged and flattened types
+//!
+//! Serde reads internally tagged and untagged enums, and structs with flattened fields, by
+//! buffering the data without knowing its types. That fails for the default option form,
+//! `[false]` or `[true, value]`, and the default enum form, `[index, value]`, since buffered
+//! they look like arrays. Types like these need `SerializerConfig::bare_options` and
+//! `SerializerConfig::named_variants` when writing, and `DeserializerConfig::bare_options` when
+//! reading. Adjacently tagged enums work with the defaults.
+//!
+//! # 128-bit integers
+//!
+//! Messagepack has no 128-bit integer type. An `i128` or `u128` that fits in 64 bits is written
+//! with the usual integer markers. Any other value is written as a 16 byte BIN holding the value
+//! in big-endian order, as two's complement for `i128`. With `SerializerConfig::exact_integers`,
+//! every 128-bit value uses the BIN form.
+//!
+//! When decoding, `i128` and `u128` accept both forms, and a BIN of any other length fails with
+//! `Error::BadLength`. The BIN form doesn't record whether it was signed, so it has to be read
+//! back as the type it was written from.
 //
 // This Source Code Form is subject to the terms of the Mozilla Public License,
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
@@ -24,11 +44,18 @@ extern crate alloc;
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
-pub use ser::Serializer;
-pub use de::Deserializer;
+pub use ser::{Serializer, SerializerConfig};
+pub use de::{Checkpoint, Deserializer, DeserializerConfig, Utf8Policy};
+pub use raw::{Raw, RawRef};
+pub use document::Document;
 
 pub mod error;
 pub mod read;
+pub mod document;
+pub mod query;
+pub mod diff;
+pub mod canonical;
+pub mod bytes;
 
 mod defs;
 mod seq_serializer;
@@ -36,6 +63,7 @@ mod map_serializer;
 mod variant_deserializer;
 mod ext_deserializer;
 mod seq_deserializer;
+mod raw;
 
 mod ser;
 mod de;
@@ -64,35 +92,65 @@ pub fn from_iter<I, V>(mut iter: I) -> Result<V, error::Error>
 pub fn from_bytes<'a, V>(bytes: &'a [u8]) -> Result<V, error::Error>
     where V: serde::Deserialize<'a>
 {
-    let mut position: usize = 0;
+    from_bytes_with_config(bytes, DeserializerConfig::default())
+}
 
-    let mut de = Deserializer::new(read::BorrowRead::new(|len: usize| if position + len >
-                                                                         bytes.len() {
-        Err(error::Error::EndOfStream)
-    } else {
-        let result = &bytes[position..position + len];
+/// Parse V out of a slice of bytes, using the given options.
+pub fn from_bytes_with_config<'a, V>(bytes: &'a [u8],
+                                     config: DeserializerConfig)
+                                     -> Result<V, error::Error>
+    where V: serde::Deserialize<'a>
+{
+    let mut de = Deserializer::with_config(read::SliceRead::new(bytes), config);
 
-        position += len;
+    V::deserialize(&mut de)
+}
 
-        Ok(result)
-    }));
+/// Parse a V out of a slice of bytes into place, reusing the buffers place already owns.
+///
+/// Strings and vectors in place keep their allocations where the type supports it. Structs
+/// from `#[derive(Deserialize)]` only do so when serde_derive's `deserialize_in_place` feature
+/// is enabled in the crate that derives them; otherwise they are replaced as a whole, like
+/// `from_bytes` would. If this fails, place is left in a valid but unspecified state.
+pub fn from_bytes_in_place<'a, V>(bytes: &'a [u8], place: &mut V) -> Result<(), error::Error>
+    where V: serde::Deserialize<'a>
+{
+    from_bytes_in_place_with_config(bytes, place, DeserializerConfig::default())
+}
 
-    V::deserialize(&mut de)
+/// Parse a V out of a slice of bytes into place, using the given options.
+pub fn from_bytes_in_place_with_config<'a, V>(bytes: &'a [u8],
+                                              place: &mut V,
+                                              config: DeserializerConfig)
+                                              -> Result<(), error::Error>
+    where V: serde::Deserialize<'a>
+{
+    let mut de = Deserializer::with_config(read::SliceRead::new(bytes), config);
+
+    V::deserialize_in_place(&mut de, place)
 }
 
 /// Serialize V into a byte buffer.
 pub fn to_bytes<V>(value: V) -> Result<Vec<u8>, error::Error>
     where V: serde::Serialize
+{
+    to_bytes_with_config(value, SerializerConfig::default())
+}
+
+/// Serialize V into a byte buffer, using the given options.
+pub fn to_bytes_with_config<V>(value: V, config: SerializerConfig) -> Result<Vec<u8>, error::Error>
+    where V: serde::Serialize
 {
     let mut bytes = vec![];
 
     {
-        let mut ser = Serializer::new(|buf| {
-            bytes.extend_from_slice(buf);
-            Ok(())
-        });
+        let mut ser = Serializer::with_config(|buf| {
+                                                  bytes.extend_from_slice(buf);
+                                                  Ok(())
+                                              },
+                                              config);
 
-        try!(value.serialize(&mut ser));
+        value.serialize(&mut ser)?;
     }
 
     Ok(bytes)
@@ -104,6 +162,7 @@ mod test {
     use serde::de::DeserializeOwned;
     use std::fmt::Debug;
     use std::ffi::CString;
+    use std::net::{IpAddr, SocketAddr};
 
     #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
     enum T {
@@ -218,4 +277,309 @@ mod test {
     fn test_float32() {
         test_through(3.2f32, &[0xca, 0x40, 0x4c, 0xcc, 0xcd])
     }
+
+    #[test]
+    fn test_ipv4() {
+        // [variant 0, [127, 0, 0, 1]]
+        test_through("127.0.0.1".parse::<IpAddr>().unwrap(),
+                     &[0x92, 0x00, 0x94, 0x7f, 0x00, 0x00, 0x01])
+    }
+
+    #[test]
+    fn test_ipv6() {
+        test_through("::1".parse::<IpAddr>().unwrap(),
+                     &[0x92, 0x01, 0xdc, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                       0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01])
+    }
+
+    #[test]
+    fn test_socket_addr() {
+        // [variant 0, [[10, 0, 0, 2], 8080]]
+        test_through("10.0.0.2:8080".parse::<SocketAddr>().unwrap(),
+                     &[0x92, 0x00, 0x92, 0x94, 0x0a, 0x00, 0x00, 0x02, 0xcd, 0x1f, 0x90])
+    }
+
+    #[test]
+    fn test_human_readable() {
+        let addr = "127.0.0.1".parse::<IpAddr>().unwrap();
+        let bytes = ::to_bytes_with_config(addr, ::SerializerConfig::new().human_readable(true))
+            .unwrap();
+
+        assert_eq!(bytes, ::to_bytes("127.0.0.1").unwrap());
+
+        let config = ::DeserializerConfig::new().human_readable(true);
+        assert_eq!(::from_bytes_with_config::<IpAddr>(&bytes, config).unwrap(), addr);
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    struct Ext {
+        #[serde(rename = "type")]
+        ty: i8,
+        #[serde(with = "::bytes")]
+        data: Vec<u8>,
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    enum Kind {
+        Plain,
+        Sized(u32),
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    struct Inner {
+        maybe: Option<u8>,
+        nothing: Option<String>,
+        kind: Kind,
+        #[serde(with = "::bytes")]
+        blob: Vec<u8>,
+    }
+
+    fn inner() -> Inner {
+        Inner {
+            maybe: Some(7),
+            nothing: None,
+            kind: Kind::Sized(12),
+            blob: vec![0xde, 0xad, 0xbe, 0xef],
+        }
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    #[serde(tag = "t")]
+    enum Internal {
+        Unit,
+        Struct { inner: Inner },
+        Newtype(Inner),
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Unit,
+        Newtype(Inner),
+        Tuple(Option<u8>, Kind),
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Untagged {
+        Number(u64),
+        Text(String),
+        Record(Inner),
+        Pair(Option<u8>, Kind),
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    struct Flattened {
+        id: u32,
+        #[serde(flatten)]
+        inner: Inner,
+        #[serde(flatten)]
+        extra: ::std::collections::BTreeMap<String, u8>,
+    }
+
+    // serde buffers tagged, untagged and flattened values, which needs the bare forms
+    fn buffered<T>(item: T)
+        where T: Serialize + DeserializeOwned + PartialEq + Debug
+    {
+        let config = ::SerializerConfig::new().bare_options(true).named_variants(true);
+        let bytes = ::to_bytes_with_config(&item, config).expect("Failed to serialize");
+
+        let config = ::DeserializerConfig::new().bare_options(true);
+        let deserialized_item: T = ::from_bytes_with_config(&bytes, config)
+            .expect("Failed to deserialize");
+
+        assert_eq!(item, deserialized_item);
+    }
+
+    fn buffered_default<T>(item: T)
+        where T: Serialize + DeserializeOwned + PartialEq + Debug
+    {
+        let bytes = ::to_bytes(&item).expect("Failed to serialize");
+        let deserialized_item: T = ::from_bytes(&bytes).expect("Failed to deserialize");
+
+        assert_eq!(item, deserialized_item);
+    }
+
+    #[test]
+    fn test_internally_tagged() {
+        buffered(Internal::Unit);
+        buffered(Internal::Struct { inner: inner() });
+        buffered(Internal::Newtype(inner()));
+    }
+
+    #[test]
+    fn test_adjacently_tagged() {
+        buffered(Adjacent::Unit);
+        buffered(Adjacent::Newtype(inner()));
+        buffered(Adjacent::Tuple(None, Kind::Plain));
+    }
+
+    #[test]
+    fn test_untagged() {
+        buffered(Untagged::Number(5));
+        buffered(Untagged::Text("five".into()));
+        buffered(Untagged::Record(inner()));
+        buffered(Untagged::Pair(Some(1), Kind::Sized(2)));
+    }
+
+    #[test]
+    fn test_flatten() {
+        let mut extra = ::std::collections::BTreeMap::new();
+        extra.insert("other".into(), 3);
+
+        buffered(Flattened {
+            id: 1,
+            inner: inner(),
+            extra,
+        });
+    }
+
+    #[test]
+    fn test_buffered_ext() {
+        #[derive(PartialEq, Debug, Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Ext(Ext),
+        }
+
+        #[derive(PartialEq, Debug, Deserialize)]
+        #[serde(tag = "t")]
+        enum Tagged {
+            Ext { value: Ext },
+        }
+
+        #[derive(PartialEq, Debug, Deserialize)]
+        struct Holder {
+            value: Ext,
+        }
+
+        #[derive(PartialEq, Debug, Deserialize)]
+        struct Flat {
+            #[serde(flatten)]
+            holder: Holder,
+        }
+
+        let ext = || {
+            Ext {
+                ty: 5,
+                data: vec![1, 2],
+            }
+        };
+
+        // fixext 2 with type 5
+        assert_eq!(::from_bytes::<Value>(&[0xd5, 0x05, 0x01, 0x02]).unwrap(),
+                   Value::Ext(ext()));
+
+        // {"t": "Ext", "value": ext}
+        let bytes = [0x82, 0xa1, 0x74, 0xa3, 0x45, 0x78, 0x74, 0xa5, 0x76, 0x61, 0x6c, 0x75, 0x65,
+                     0xd5, 0x05, 0x01, 0x02];
+        assert_eq!(::from_bytes::<Tagged>(&bytes).unwrap(), Tagged::Ext { value: ext() });
+
+        // {"value": ext}
+        let mut bytes = bytes[7..].to_vec();
+        bytes.insert(0, 0x81);
+        assert_eq!(::from_bytes::<Flat>(&bytes).unwrap(),
+                   Flat { holder: Holder { value: ext() } });
+    }
+
+    #[test]
+    fn test_bare_forms() {
+        let config = ::SerializerConfig::new().bare_options(true).named_variants(true);
+
+        assert_eq!(::to_bytes_with_config(Some(5), config).unwrap(), &[0x05]);
+        assert_eq!(::to_bytes_with_config(None::<u8>, config).unwrap(), &[0xc0]);
+        assert_eq!(::to_bytes_with_config(T::B, config).unwrap(), &[0xa1, 0x42]);
+        assert_eq!(::to_bytes_with_config(T::A(1), config).unwrap(),
+                   &[0x81, 0xa1, 0x41, 0x01]);
+
+        // named variants read back without any options
+        test_through(T::C(1, 2), &[0x92, 0x02, 0x92, 0x01, 0x02]);
+        assert_eq!(::from_bytes::<T>(&::to_bytes_with_config(T::C(1, 2), config).unwrap())
+                       .unwrap(),
+                   T::C(1, 2));
+
+        let config = ::DeserializerConfig::new().bare_options(true);
+        assert_eq!(::from_bytes_with_config::<Option<u8>>(&[0x05], config).unwrap(), Some(5));
+        assert_eq!(::from_bytes_with_config::<Option<Vec<u8>>>(&[0x92, 0x01, 0x02], config)
+                       .unwrap(),
+                   Some(vec![1, 2]));
+
+        assert_eq!(::from_bytes_with_config::<Option<Vec<bool>>>(&[0x91, 0xc2], config).unwrap(),
+                   Some(vec![false]));
+
+        // values that look like the tuple forms aren't mistaken for them
+        buffered(Some(vec![false]));
+        buffered(Some((true, 5u8)));
+        buffered(vec![None, Some(vec![false]), Some(vec![true])]);
+    }
+
+    #[test]
+    fn test_buffered_default() {
+        // serde's buffering can't tell the default option and enum forms from arrays
+        let bytes = ::to_bytes(Internal::Newtype(inner())).unwrap();
+        assert!(::from_bytes::<Internal>(&bytes).is_err());
+
+        let bytes = ::to_bytes(Untagged::Record(inner())).unwrap();
+        assert!(::from_bytes::<Untagged>(&bytes).is_err());
+
+        let bytes = ::to_bytes(Flattened {
+                id: 1,
+                inner: inner(),
+                extra: ::std::collections::BTreeMap::new(),
+            })
+            .unwrap();
+        assert!(::from_bytes::<Flattened>(&bytes).is_err());
+
+        // types without options or enums inside work either way
+        buffered_default(Internal::Unit);
+        buffered_default(Untagged::Number(5));
+        buffered_default(Untagged::Text("five".into()));
+        buffered_default(Adjacent::Newtype(inner()));
+        buffered_default(Adjacent::Tuple(None, Kind::Plain));
+    }
+
+    #[derive(PartialEq, Debug, Serialize, Deserialize)]
+    struct Message {
+        id: u32,
+        name: String,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_in_place() {
+        let first = ::to_bytes(Message {
+                id: 1,
+                name: "a fairly long first name".into(),
+                tags: vec!["one".into(), "two".into(), "three".into()],
+            })
+            .unwrap();
+        let second = ::to_bytes(Message {
+                id: 2,
+                name: "second".into(),
+                tags: vec!["four".into(), "five".into()],
+            })
+            .unwrap();
+
+        let mut message: Message = ::from_bytes(&first).unwrap();
+        let name = message.name.as_ptr();
+        let tags = message.tags.as_ptr();
+        let tag = message.tags[0].as_ptr();
+
+        ::from_bytes_in_place(&second, &mut message).unwrap();
+
+        assert_eq!(message,
+                   Message {
+                       id: 2,
+                       name: "second".into(),
+                       tags: vec!["four".into(), "five".into()],
+                   });
+        assert_eq!(message.name.as_ptr(), name);
+        assert_eq!(message.tags.as_ptr(), tags);
+        assert_eq!(message.tags[0].as_ptr(), tag);
+
+        let config = ::DeserializerConfig::new().strict(true);
+        ::from_bytes_in_place_with_config(&first, &mut message, config).unwrap();
+        assert_eq!(message.id, 1);
+        assert_eq!(message.tags.as_ptr(), tags);
+    }
 }
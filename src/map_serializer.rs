@@ -10,7 +10,7 @@ use serde::ser::{Serialize, SerializeMap, SerializeStruct, SerializeStructVarian
 
 use byteorder::{ByteOrder, BigEndian};
 
-use ser::Serializer;
+use ser::{Serializer, SerializerConfig};
 
 use defs::*;
 use error::Error;
@@ -19,27 +19,31 @@ pub struct MapSerializer<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> {
     count: usize,
     size: Option<usize>,
     buffer: Vec<u8>,
+    // start of each key and value in buffer, used to sort canonical maps
+    offsets: Vec<usize>,
     output: &'a mut F,
+    config: SerializerConfig,
 }
 
 impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> MapSerializer<'a, F> {
-    pub fn new(output: &'a mut F) -> MapSerializer<'a, F> {
+    pub fn new(output: &'a mut F, config: SerializerConfig) -> MapSerializer<'a, F> {
         MapSerializer {
             count: 0,
             size: None,
             buffer: vec![],
-            output: output,
+            offsets: vec![],
+            output,
+            config,
         }
     }
 
     pub fn hint_size(&mut self, size: Option<usize>) -> Result<(), Error> {
         self.size = size;
 
-        if let Some(size) = self.size {
+        match self.size {
             // output this now because we know it
-            self.output_map_header(size)
-        } else {
-            Ok(())
+            Some(size) if self.should_serialize_directly() => self.output_map_header(size),
+            _ => Ok(()),
         }
     }
 
@@ -58,14 +62,48 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> MapSerializer<'a, F> {
     fn finish(mut self) -> Result<(), Error> {
         if let Some(size) = self.size {
             self.check_item_count_matches_size(size * 2)?;
-            Ok(())
+        }
+
+        if self.should_serialize_directly() {
+            return Ok(());
+        }
+
+        let count = self.get_item_count()?;
+        self.output_map_header(count)?;
+
+        if self.config.is_canonical() {
+            self.output_sorted_entries()
         } else {
-            let count = self.get_item_count()?;
-            self.output_map_header(count)?;
             (self.output)(&*self.buffer)
         }
     }
 
+    fn output_sorted_entries(&mut self) -> Result<(), Error> {
+        self.offsets.push(self.buffer.len());
+
+        let buffer = &self.buffer;
+        let offsets = &self.offsets;
+        let mut entries: Vec<(&[u8], &[u8])> = (0..offsets.len() / 2)
+            .map(|i| {
+                let (key, value, end) = (offsets[2 * i], offsets[2 * i + 1], offsets[2 * i + 2]);
+                (&buffer[key..value], &buffer[value..end])
+            })
+            .collect();
+
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::DuplicateKey);
+        }
+
+        for (key, value) in entries {
+            (self.output)(key)?;
+            (self.output)(value)?;
+        }
+
+        Ok(())
+    }
+
     fn output_map_header(&mut self, size: usize) -> Result<(), Error> {
         if size <= MAX_FIXMAP {
             (self.output)(&[size as u8 | FIXMAP_MASK])
@@ -83,7 +121,7 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> MapSerializer<'a, F> {
     }
 
     fn get_item_count(&self) -> Result<usize, Error> {
-        if self.count % 1 != 0 {
+        if self.count % 2 != 0 {
             Err(Error::BadLength)
         } else {
             Ok(self.count / 2)
@@ -98,17 +136,22 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> MapSerializer<'a, F> {
         }
     }
 
-    fn should_serialize_directly(&mut self) -> bool {
-        self.size.is_some()
+    fn should_serialize_directly(&self) -> bool {
+        // canonical maps have to be sorted before they can be written
+        self.size.is_some() && !self.config.is_canonical()
     }
 
     fn serialize_into_buffer<T>(&mut self, value: &T) -> Result<(), Error>
         where T: ?Sized + Serialize
     {
-        let mut target = Serializer::new(|bytes| {
-            self.buffer.extend_from_slice(bytes);
-            Ok(())
-        });
+        self.offsets.push(self.buffer.len());
+
+        let buffer = &mut self.buffer;
+        let mut target = Serializer::with_config(|bytes| {
+                                                     buffer.extend_from_slice(bytes);
+                                                     Ok(())
+                                                 },
+                                                 self.config);
 
         value.serialize(&mut target)
     }
@@ -116,7 +159,8 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> MapSerializer<'a, F> {
     fn serialize_directly<T>(&mut self, value: &T) -> Result<(), Error>
         where T: ?Sized + Serialize
     {
-        let mut target = Serializer::new(|bytes| (self.output)(bytes));
+        let output = &mut self.output;
+        let mut target = Serializer::with_config(|bytes| (output)(bytes), self.config);
 
         value.serialize(&mut target)
     }
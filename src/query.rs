@@ -0,0 +1,310 @@
+//! Extract or replace single values in encoded data by path.
+//!
+//! Paths use JSON Pointer syntax: `/orders/3/price` selects the `price` entry of the fourth
+//! element of the `orders` entry. Map keys are matched against string keys, or against integer
+//! keys when the segment is a number. `~1` and `~0` escape `/` and `~` within a segment, and the
+//! empty path selects the whole value.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use std::ops::Range;
+
+use serde::{Serialize, Deserialize};
+
+use document::Document;
+use error::Error;
+
+// one unparsed segment of a path, still containing escapes
+struct Segment<'p> {
+    raw: &'p str,
+}
+
+/// Deserialize the value at path.
+///
+/// Returns `Ok(None)` if nothing exists at path, and an error if the value exists but cannot be
+/// deserialized as V. Values off the path are skipped without being decoded.
+pub fn get<'a, V>(bytes: &'a [u8], path: &str) -> Result<Option<V>, Error>
+    where V: Deserialize<'a>
+{
+    match find(bytes, path)? {
+        Some(doc) => doc.decode().map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Find the value at path without decoding it.
+pub fn find<'a>(bytes: &'a [u8], path: &str) -> Result<Option<Document<'a>>, Error> {
+    let mut doc = Document::new(bytes);
+
+    for segment in segments(path)? {
+        match lookup(doc, segment)? {
+            Some(child) => doc = child,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(doc))
+}
+
+/// Replace the value at path with value, leaving the rest of the encoded data untouched.
+///
+/// Returns false, and leaves bytes unchanged, if nothing exists at path. No container headers
+/// need to change, since replacing a value does not change the number of items in any container.
+pub fn patch<V>(bytes: &mut Vec<u8>, path: &str, value: &V) -> Result<bool, Error>
+    where V: ?Sized + Serialize
+{
+    let range = match locate(bytes, path)? {
+        Some(range) => range,
+        None => return Ok(false),
+    };
+
+    let replacement = ::to_bytes(value)?;
+    bytes.splice(range, replacement);
+
+    Ok(true)
+}
+
+// finds the position of the encoded value at path
+fn locate(bytes: &[u8], path: &str) -> Result<Option<Range<usize>>, Error> {
+    let doc = match find(bytes, path)? {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+
+    let start = bytes.len() - doc.tail().len();
+    let end = start + doc.encoded()?.len();
+
+    Ok(Some(start..end))
+}
+
+// parses the whole path up front, so a bad segment is reported even if an earlier one misses
+fn segments(path: &str) -> Result<Vec<Segment<'_>>, Error> {
+    let mut split = path.split('/');
+
+    // the empty path has no segments, anything else has to start with a slash
+    match split.next() {
+        Some("") => {}
+        _ => return Err(Error::BadPath),
+    }
+
+    split.map(Segment::new).collect()
+}
+
+fn lookup<'a>(doc: Document<'a>, segment: Segment) -> Result<Option<Document<'a>>, Error> {
+    if doc.is_array() {
+        return match segment.index() {
+            Some(index) => doc.index(index),
+            None => Ok(None),
+        };
+    }
+
+    for entry in doc.entries()? {
+        let (key, value) = entry?;
+
+        if segment.matches(key) {
+            return Ok(Some(value));
+        }
+    }
+
+    Ok(None)
+}
+
+impl<'p> Segment<'p> {
+    fn new(raw: &'p str) -> Result<Segment<'p>, Error> {
+        let mut chars = raw.chars();
+
+        while let Some(c) = chars.next() {
+            if c == '~' && !matches!(chars.next(), Some('0') | Some('1')) {
+                return Err(Error::BadPath);
+            }
+        }
+
+        Ok(Segment { raw })
+    }
+
+    fn unescaped(&self) -> impl Iterator<Item = char> + 'p {
+        let mut chars = self.raw.chars();
+
+        ::std::iter::from_fn(move || {
+            chars.next().map(|c| match c {
+                '~' => if chars.next() == Some('1') { '/' } else { '~' },
+                c => c,
+            })
+        })
+    }
+
+    fn index(&self) -> Option<usize> {
+        if !is_canonical_number(self.raw) {
+            return None;
+        }
+
+        self.raw.parse().ok()
+    }
+
+    // integer map keys are written like indices, but may be negative
+    fn integer(&self) -> Option<i64> {
+        let digits = if self.raw.starts_with('-') { &self.raw[1..] } else { self.raw };
+        if !is_canonical_number(digits) || self.raw == "-0" {
+            return None;
+        }
+
+        self.raw.parse().ok()
+    }
+
+    fn matches(&self, key: Document) -> bool {
+        if let Some(key) = key.as_str() {
+            return self.unescaped().eq(key.chars());
+        }
+
+        match (self.integer(), key.as_i64()) {
+            (Some(segment), Some(key)) => segment == key,
+            _ => false,
+        }
+    }
+}
+
+// like JSON Pointer, numbers are plain digits without leading zeros
+fn is_canonical_number(digits: &str) -> bool {
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) &&
+    (digits.len() == 1 || !digits.starts_with('0'))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use error::Error;
+
+    use super::{get, patch};
+
+    #[derive(Serialize)]
+    struct Order {
+        id: u32,
+        price: f64,
+    }
+
+    #[derive(Serialize)]
+    struct Shop {
+        name: &'static str,
+        orders: Vec<Order>,
+        #[serde(rename = "a/b~c")]
+        escaped: bool,
+        by_id: BTreeMap<u32, &'static str>,
+    }
+
+    fn fixture() -> Vec<u8> {
+        let mut by_id = BTreeMap::new();
+        by_id.insert(7, "seven");
+
+        ::to_bytes(Shop {
+                name: "shop",
+                orders: (0..5)
+                    .map(|id| {
+                        Order {
+                            id,
+                            price: id as f64 * 1.5,
+                        }
+                    })
+                    .collect(),
+                escaped: true,
+                by_id,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn get_test() {
+        let bytes = fixture();
+
+        assert_eq!(get::<f64>(&bytes, "/orders/3/price").unwrap(), Some(4.5));
+        assert_eq!(get::<&str>(&bytes, "/name").unwrap(), Some("shop"));
+        assert_eq!(get::<bool>(&bytes, "/a~1b~0c").unwrap(), Some(true));
+        assert_eq!(get::<String>(&bytes, "/by_id/7").unwrap(), Some("seven".into()));
+    }
+
+    #[test]
+    fn whole_document_test() {
+        let bytes = ::to_bytes(vec![1, 2, 3]).unwrap();
+
+        assert_eq!(get::<Vec<u8>>(&bytes, "").unwrap(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn missing_test() {
+        let bytes = fixture();
+
+        assert_eq!(get::<f64>(&bytes, "/orders/5/price").unwrap(), None);
+        assert_eq!(get::<f64>(&bytes, "/orders/x").unwrap(), None);
+        assert_eq!(get::<f64>(&bytes, "/nothing").unwrap(), None);
+        assert_eq!(get::<f64>(&bytes, "/name/0").unwrap(), None);
+        assert_eq!(get::<f64>(&bytes, "/orders/03/price").unwrap(), None);
+        assert_eq!(get::<&str>(&bytes, "/by_id/7").unwrap(), Some("seven"));
+        assert_eq!(get::<&str>(&bytes, "/by_id/07").unwrap(), None);
+        assert_eq!(get::<&str>(&bytes, "/by_id/+7").unwrap(), None);
+    }
+
+    #[test]
+    fn mismatch_test() {
+        let bytes = fixture();
+
+        assert!(get::<u64>(&bytes, "/name").is_err());
+    }
+
+    #[test]
+    fn bad_path_test() {
+        let bytes = fixture();
+
+        match get::<u64>(&bytes, "name") {
+            Err(Error::BadPath) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match get::<u64>(&bytes, "/a~2") {
+            Err(Error::BadPath) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // the bad escape comes after a segment that already misses
+        match get::<u64>(&bytes, "/nothing/a~2") {
+            Err(Error::BadPath) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn patch_test() {
+        let mut bytes = fixture();
+
+        assert!(patch(&mut bytes, "/orders/3/price", &9001.5).unwrap());
+        assert!(patch(&mut bytes, "/name", "a much longer name than before").unwrap());
+        assert!(patch(&mut bytes, "/orders/1", &(1, 2, 3)).unwrap());
+
+        assert_eq!(get::<f64>(&bytes, "/orders/3/price").unwrap(), Some(9001.5));
+        assert_eq!(get::<f64>(&bytes, "/orders/4/price").unwrap(), Some(6.0));
+        assert_eq!(get::<&str>(&bytes, "/name").unwrap(),
+                   Some("a much longer name than before"));
+        assert_eq!(get::<Vec<u8>>(&bytes, "/orders/1").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(get::<bool>(&bytes, "/a~1b~0c").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn patch_missing_test() {
+        let mut bytes = fixture();
+        let original = bytes.clone();
+
+        assert!(!patch(&mut bytes, "/orders/9/price", &1).unwrap());
+        assert_eq!(bytes, original);
+    }
+
+    #[test]
+    fn patch_root_test() {
+        let mut bytes = ::to_bytes(7).unwrap();
+
+        assert!(patch(&mut bytes, "", "seven").unwrap());
+        assert_eq!(bytes, ::to_bytes("seven").unwrap());
+    }
+}
@@ -0,0 +1,270 @@
+//! Values that are kept in their encoded form.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at https://mozilla.org/MPL/2.0/.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use serde;
+
+use de::value_len;
+use defs::RAW_TOKEN;
+use error::Error;
+
+/// An owned, undecoded messagepack value.
+///
+/// Deserializing a `Raw` captures the exact bytes of one value, and serializing it writes those
+/// bytes back out unchanged. This only works with corepack's own Serializer and Deserializer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Raw {
+    bytes: Vec<u8>,
+}
+
+/// A borrowed, undecoded messagepack value.
+///
+/// Like `Raw`, but borrows from the input. Decoding one requires input that can be borrowed,
+/// such as the slice passed to `from_bytes`. With a `BorrowRead`, the whole value has to come
+/// from a single slice returned by its closure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RawRef<'de> {
+    bytes: &'de [u8],
+}
+
+// wrapper that hands encoded bytes to serialize_bytes
+pub(crate) struct Encoded<'a>(pub(crate) &'a [u8]);
+
+struct RawVisitor;
+
+struct RawRefVisitor;
+
+impl Raw {
+    /// Wrap bytes that contain exactly one encoded value.
+    pub fn new(bytes: Vec<u8>) -> Result<Raw, Error> {
+        check_single_value(&bytes)?;
+
+        Ok(Raw { bytes })
+    }
+
+    /// Encode a value and keep the result.
+    pub fn from_value<V>(value: &V) -> Result<Raw, Error>
+        where V: ?Sized + Serialize
+    {
+        Ok(Raw { bytes: ::to_bytes(value)? })
+    }
+
+    /// The encoded bytes of the value.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Take the encoded bytes of the value.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Borrow this value as a `RawRef`.
+    pub fn as_raw_ref(&self) -> RawRef<'_> {
+        RawRef { bytes: &self.bytes }
+    }
+
+    /// Decode the captured value.
+    pub fn decode<'a, V>(&'a self) -> Result<V, Error>
+        where V: Deserialize<'a>
+    {
+        ::from_bytes(&self.bytes)
+    }
+}
+
+impl<'de> RawRef<'de> {
+    /// Wrap bytes that contain exactly one encoded value.
+    pub fn new(bytes: &'de [u8]) -> Result<RawRef<'de>, Error> {
+        check_single_value(bytes)?;
+
+        Ok(RawRef { bytes })
+    }
+
+    /// The encoded bytes of the value.
+    pub fn as_bytes(&self) -> &'de [u8] {
+        self.bytes
+    }
+
+    /// Copy this value into a `Raw`.
+    pub fn to_raw(&self) -> Raw {
+        Raw { bytes: self.bytes.to_vec() }
+    }
+
+    /// Decode the captured value.
+    pub fn decode<V>(&self) -> Result<V, Error>
+        where V: Deserialize<'de>
+    {
+        ::from_bytes(self.bytes)
+    }
+}
+
+fn check_single_value(bytes: &[u8]) -> Result<(), Error> {
+    if value_len(bytes)? != bytes.len() {
+        Err(Error::BadLength)
+    } else {
+        Ok(())
+    }
+}
+
+impl<'a> Serialize for Encoded<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl Serialize for Raw {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_newtype_struct(RAW_TOKEN, &Encoded(&self.bytes))
+    }
+}
+
+impl<'de> Serialize for RawRef<'de> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_newtype_struct(RAW_TOKEN, &Encoded(self.bytes))
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for RawVisitor {
+    type Value = Raw;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a raw messagepack value")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Raw, E>
+        where E: serde::de::Error
+    {
+        Ok(Raw { bytes: v.to_vec() })
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Raw, E>
+        where E: serde::de::Error
+    {
+        Ok(Raw { bytes: v })
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for RawRefVisitor {
+    type Value = RawRef<'de>;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a borrowed raw messagepack value")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<RawRef<'de>, E>
+        where E: serde::de::Error
+    {
+        Ok(RawRef { bytes: v })
+    }
+}
+
+impl<'de> Deserialize<'de> for Raw {
+    fn deserialize<D>(deserializer: D) -> Result<Raw, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        deserializer.deserialize_newtype_struct(RAW_TOKEN, RawVisitor)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawRef<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<RawRef<'a>, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        deserializer.deserialize_newtype_struct(RAW_TOKEN, RawRefVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::{Raw, RawRef};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Envelope<P> {
+        route: String,
+        payload: P,
+    }
+
+    // {"route": "a", "payload": {"x": [1, "two"]}}
+    const FIXTURE: &[u8] = &[0x82, 0xa5, 0x72, 0x6f, 0x75, 0x74, 0x65, 0xa1, 0x61, 0xa7, 0x70,
+                             0x61, 0x79, 0x6c, 0x6f, 0x61, 0x64, 0x81, 0xa1, 0x78, 0x92, 0x01,
+                             0xa3, 0x74, 0x77, 0x6f];
+
+    const PAYLOAD: &[u8] = &[0x81, 0xa1, 0x78, 0x92, 0x01, 0xa3, 0x74, 0x77, 0x6f];
+
+    #[test]
+    fn raw_ref_test() {
+        let envelope: Envelope<RawRef> = ::from_bytes(FIXTURE).unwrap();
+        assert_eq!(envelope.route, "a");
+        assert_eq!(envelope.payload.as_bytes(), PAYLOAD);
+        assert_eq!(::to_bytes(&envelope).unwrap(), FIXTURE);
+    }
+
+    #[test]
+    fn raw_from_iter_test() {
+        let envelope: Envelope<Raw> = ::from_iter(FIXTURE.iter().cloned()).unwrap();
+        assert_eq!(envelope.payload.as_bytes(), PAYLOAD);
+        assert_eq!(::to_bytes(&envelope).unwrap(), FIXTURE);
+    }
+
+    #[test]
+    fn raw_ref_needs_borrowed_input_test() {
+        let mut position = 0;
+        let mut de = ::Deserializer::new(::read::CopyRead::new(|buf: &mut [u8]| {
+            buf.copy_from_slice(&FIXTURE[position..position + buf.len()]);
+            position += buf.len();
+            Ok(())
+        }));
+
+        assert!(Envelope::<RawRef>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn raw_ref_from_borrow_read_test() {
+        let mut rest = FIXTURE;
+        let mut de = ::Deserializer::new(::read::BorrowRead::new(|_| {
+            Ok(::std::mem::take(&mut rest))
+        }));
+
+        let envelope = Envelope::<RawRef>::deserialize(&mut de).unwrap();
+        assert_eq!(envelope.payload.as_bytes(), PAYLOAD);
+
+        // a value split across slices can't be borrowed
+        let mut rest = FIXTURE;
+        let mut de = ::Deserializer::new(::read::BorrowRead::new(|len| {
+            let (data, tail) = rest.split_at(len);
+            rest = tail;
+            Ok(data)
+        }));
+
+        assert!(Envelope::<RawRef>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn raw_decode_test() {
+        let raw = Raw::from_value(&(1u8, "two")).unwrap();
+        assert_eq!(raw.decode::<(u8, String)>().unwrap(), (1, "two".into()));
+        assert_eq!(raw.as_raw_ref(), RawRef::new(&[0x92, 0x01, 0xa3, 0x74, 0x77, 0x6f]).unwrap());
+    }
+
+    #[test]
+    fn raw_new_rejects_trailing_test() {
+        assert!(Raw::new(vec![0x01, 0x02]).is_err());
+        assert!(Raw::new(vec![0x92, 0x01]).is_err());
+    }
+}
@@ -10,6 +10,12 @@ use alloc::vec::Vec;
 
 use error::Error;
 
+// size of the stack buffer used to discard copied data
+const SKIP_CHUNK: usize = 64;
+
+/// How much data CopyRead keeps by default for rewinding to a checkpoint
+pub const REPLAY_LIMIT: usize = 64 * 1024;
+
 /// The trait used by Deserializer to read input data
 pub trait Read<'de>: private::Sealed {
     /// Reads the next len bytes of data, either by borowing or copying
@@ -17,6 +23,27 @@ pub trait Read<'de>: private::Sealed {
                  len: usize,
                  scratch: &'a mut Vec<u8>)
                  -> Result<Reference<'de, 'a>, Error>;
+
+    /// Returns the next byte of data without consuming it
+    fn peek(&mut self) -> Result<u8, Error>;
+
+    /// Discards the next len bytes of data without copying them
+    fn skip(&mut self, len: usize) -> Result<(), Error>;
+
+    /// Starts recording all data consumed from this point on. Captures can be nested.
+    fn begin_capture(&mut self);
+
+    /// Stops the innermost capture and returns the data consumed since its `begin_capture`
+    fn end_capture<'a>(&mut self, scratch: &'a mut Vec<u8>) -> Reference<'de, 'a>;
+
+    /// Saves the current position and returns its depth. Checkpoints can be nested.
+    fn checkpoint(&mut self) -> usize;
+
+    /// Returns to the checkpoint at depth, dropping it and every checkpoint inside it
+    fn rewind(&mut self, depth: usize) -> Result<(), Error>;
+
+    /// Drops the checkpoint at depth and every checkpoint inside it, without moving
+    fn release(&mut self, depth: usize);
 }
 
 /// Data that was copied or borrowed
@@ -25,14 +52,57 @@ pub enum Reference<'de, 'a> {
     Copied(&'a [u8]),
 }
 
+/// Reader over a slice that is fully available in memory
+pub struct SliceRead<'de> {
+    bytes: &'de [u8],
+    position: usize,
+    capture_starts: Vec<usize>,
+    checkpoints: Vec<usize>,
+}
+
 /// Wrapper object around a closure that provides borrowed data
+///
+/// The closure is given the number of bytes needed next, and may return more than that. The
+/// rest is handed out by later reads and peeks without calling it again, so a closure that
+/// returns all of its data at once never has to copy.
 pub struct BorrowRead<'de, F: FnMut(usize) -> Result<&'de [u8], Error>> {
     thunk: F,
+    // slices from the closure that are still being read, captured, or may be read again after a
+    // rewind
+    chunks: Vec<&'de [u8]>,
+    // the chunk being read, and how far into it
+    chunk: usize,
+    offset: usize,
+    // positions saved by begin_capture and checkpoint, innermost last
+    capture_starts: Vec<(usize, usize)>,
+    checkpoints: Vec<(usize, usize)>,
 }
 
 /// Wrapper object around a closure that provides copied data
 pub struct CopyRead<F: FnMut(&mut [u8]) -> Result<(), Error>> {
     thunk: F,
+    peeked: Option<u8>,
+    capture: Capture,
+    replay: Replay,
+}
+
+/// Copy of the data consumed by a closure-based reader while capturing
+struct Capture {
+    // where each active capture starts in buffer, innermost last
+    starts: Vec<usize>,
+    buffer: Vec<u8>,
+}
+
+/// Copy of the data consumed by a closure-based reader since its outermost checkpoint
+struct Replay {
+    // where each active checkpoint starts in buffer, innermost last
+    starts: Vec<usize>,
+    buffer: Vec<u8>,
+    // how much of buffer has been consumed, which is less than all of it after a rewind
+    position: usize,
+    limit: usize,
+    // set once buffer would have grown past limit, until every checkpoint is dropped
+    overflowed: bool,
 }
 
 impl<'de, 'a> Deref for Reference<'de, 'a> {
@@ -46,25 +116,392 @@ impl<'de, 'a> Deref for Reference<'de, 'a> {
     }
 }
 
+impl Capture {
+    fn new() -> Capture {
+        Capture {
+            starts: vec![],
+            buffer: vec![],
+        }
+    }
+
+    fn record(&mut self, data: &[u8]) {
+        if !self.starts.is_empty() {
+            self.buffer.extend_from_slice(data);
+        }
+    }
+
+    fn begin(&mut self) {
+        self.starts.push(self.buffer.len());
+    }
+
+    fn end<'de, 'a>(&mut self, scratch: &'a mut Vec<u8>) -> Reference<'de, 'a> {
+        let start = self.starts.pop().unwrap_or(0).min(self.buffer.len());
+
+        // copy rather than hand over the buffer, so both keep their allocations for later
+        scratch.clear();
+        scratch.extend_from_slice(&self.buffer[start..]);
+
+        if self.starts.is_empty() {
+            self.buffer.clear();
+        }
+
+        Reference::Copied(scratch)
+    }
+
+    // forgets the last len bytes recorded, after a rewind
+    fn unrecord(&mut self, len: usize) {
+        let end = self.buffer.len().saturating_sub(len);
+        self.buffer.truncate(end);
+
+        for start in self.starts.iter_mut() {
+            *start = (*start).min(end);
+        }
+    }
+}
+
+impl Replay {
+    fn new() -> Replay {
+        Replay {
+            starts: vec![],
+            buffer: vec![],
+            position: 0,
+            limit: REPLAY_LIMIT,
+            overflowed: false,
+        }
+    }
+
+    fn replaying(&self) -> bool {
+        self.position < self.buffer.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.buffer.get(self.position).cloned()
+    }
+
+    // consumes up to len bytes of rewound data, appending them to out, and returns how many
+    fn take(&mut self, len: usize, out: &mut Vec<u8>) -> usize {
+        let end = self.buffer.len().min(self.position + len);
+        let taken = end - self.position;
+
+        out.extend_from_slice(&self.buffer[self.position..end]);
+        self.position = end;
+        self.tidy();
+
+        taken
+    }
+
+    // records data freshly consumed from the closure, which only happens once replaying is done
+    fn record(&mut self, data: &[u8]) {
+        if self.starts.is_empty() || self.overflowed || data.is_empty() {
+            return;
+        }
+
+        if self.buffer.len() + data.len() > self.limit {
+            self.overflowed = true;
+            self.buffer.clear();
+        } else {
+            self.buffer.extend_from_slice(data);
+        }
+
+        self.position = self.buffer.len();
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.starts.push(self.position);
+        self.starts.len() - 1
+    }
+
+    // moves back to the checkpoint at depth, and returns how far
+    fn rewind(&mut self, depth: usize) -> Result<usize, Error> {
+        if depth >= self.starts.len() || self.overflowed {
+            // the checkpoint is gone either way, and the caller can't release it anymore
+            self.release(depth);
+            return Err(Error::BadCheckpoint);
+        }
+
+        let start = self.starts[depth];
+        let distance = self.position - start;
+
+        self.position = start;
+        self.starts.truncate(depth);
+
+        Ok(distance)
+    }
+
+    fn release(&mut self, depth: usize) {
+        self.starts.truncate(depth);
+
+        if self.starts.is_empty() {
+            self.overflowed = false;
+            self.tidy();
+        }
+    }
+
+    // drops the recording once nothing can go back to it
+    fn tidy(&mut self) {
+        if self.starts.is_empty() && !self.replaying() {
+            self.buffer.clear();
+            self.position = 0;
+        }
+    }
+}
+
+impl<'de> SliceRead<'de> {
+    pub fn new(bytes: &'de [u8]) -> SliceRead<'de> {
+        SliceRead {
+            bytes,
+            position: 0,
+            capture_starts: vec![],
+            checkpoints: vec![],
+        }
+    }
+
+    /// The number of bytes consumed so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
 impl<'de, F: FnMut(usize) -> Result<&'de [u8], Error>> BorrowRead<'de, F> {
     pub fn new(thunk: F) -> BorrowRead<'de, F> {
-        BorrowRead { thunk: thunk }
+        BorrowRead {
+            thunk,
+            chunks: vec![],
+            chunk: 0,
+            offset: 0,
+            capture_starts: vec![],
+            checkpoints: vec![],
+        }
+    }
+
+    // the unread part of the current chunk, moving on to the next one once it is used up
+    fn current(&mut self) -> &'de [u8] {
+        while self.chunk < self.chunks.len() {
+            let chunk: &'de [u8] = self.chunks[self.chunk];
+
+            if self.offset < chunk.len() {
+                return &chunk[self.offset..];
+            }
+
+            if self.chunk + 1 == self.chunks.len() {
+                break;
+            }
+
+            self.chunk += 1;
+            self.offset = 0;
+        }
+
+        &[]
+    }
+
+    // asks the closure for len more bytes, once every chunk has been read
+    fn fetch(&mut self, len: usize) -> Result<&'de [u8], Error> {
+        if self.checkpoints.is_empty() && self.capture_starts.is_empty() {
+            // nothing can go back to the old chunks
+            self.chunks.clear();
+        }
+
+        let data = (self.thunk)(len)?;
+        if data.is_empty() {
+            return Err(Error::EndOfStream);
+        }
+
+        self.chunks.push(data);
+        self.chunk = self.chunks.len() - 1;
+        self.offset = 0;
+        Ok(data)
+    }
+
+    // consumes up to len bytes from a single chunk
+    fn take(&mut self, len: usize) -> Result<&'de [u8], Error> {
+        let mut current = self.current();
+        if current.is_empty() {
+            current = self.fetch(len)?;
+        }
+
+        let taken = &current[..len.min(current.len())];
+        self.offset += taken.len();
+        Ok(taken)
     }
 }
 
 impl<F: FnMut(&mut [u8]) -> Result<(), Error>> CopyRead<F> {
     pub fn new(thunk: F) -> CopyRead<F> {
-        CopyRead { thunk: thunk }
+        CopyRead {
+            thunk,
+            peeked: None,
+            capture: Capture::new(),
+            replay: Replay::new(),
+        }
+    }
+
+    /// Set how much data is kept for rewinding to a checkpoint, `REPLAY_LIMIT` by default.
+    ///
+    /// Rewinding fails with `BadCheckpoint` once more than limit bytes are read past the
+    /// outermost checkpoint.
+    pub fn replay_limit(mut self, limit: usize) -> CopyRead<F> {
+        self.replay.limit = limit;
+        self
     }
 }
 
+impl<'de> private::Sealed for SliceRead<'de> {}
+
 impl<'de, F: FnMut(usize) -> Result<&'de [u8], Error>> private::Sealed for BorrowRead<'de, F> {}
 
 impl<F: FnMut(&mut [u8]) -> Result<(), Error>> private::Sealed for CopyRead<F> {}
 
-impl<'de, F: FnMut(usize) -> Result<&'de [u8], Error>> Read<'de> for BorrowRead<'de, F> {
+impl<'de> Read<'de> for SliceRead<'de> {
     fn input<'a>(&mut self, len: usize, _: &'a mut Vec<u8>) -> Result<Reference<'de, 'a>, Error> {
-        Ok(Reference::Borrowed((self.thunk)(len)?))
+        if len > self.bytes.len() - self.position {
+            return Err(Error::EndOfStream);
+        }
+
+        let result = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(Reference::Borrowed(result))
+    }
+
+    fn peek(&mut self) -> Result<u8, Error> {
+        self.bytes.get(self.position).cloned().ok_or(Error::EndOfStream)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Error> {
+        if len > self.bytes.len() - self.position {
+            return Err(Error::EndOfStream);
+        }
+
+        self.position += len;
+        Ok(())
+    }
+
+    fn begin_capture(&mut self) {
+        self.capture_starts.push(self.position);
+    }
+
+    fn end_capture<'a>(&mut self, _: &'a mut Vec<u8>) -> Reference<'de, 'a> {
+        let start = self.capture_starts.pop().unwrap_or(self.position).min(self.position);
+        Reference::Borrowed(&self.bytes[start..self.position])
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.checkpoints.push(self.position);
+        self.checkpoints.len() - 1
+    }
+
+    fn rewind(&mut self, depth: usize) -> Result<(), Error> {
+        let position = *self.checkpoints.get(depth).ok_or(Error::BadCheckpoint)?;
+
+        self.position = position;
+        self.checkpoints.truncate(depth);
+        Ok(())
+    }
+
+    fn release(&mut self, depth: usize) {
+        self.checkpoints.truncate(depth);
+    }
+}
+
+impl<'de, F: FnMut(usize) -> Result<&'de [u8], Error>> Read<'de> for BorrowRead<'de, F> {
+    fn input<'a>(&mut self,
+                 len: usize,
+                 scratch: &'a mut Vec<u8>)
+                 -> Result<Reference<'de, 'a>, Error> {
+        if len == 0 {
+            return Ok(Reference::Borrowed(&[]));
+        }
+
+        let first = self.take(len)?;
+        if first.len() == len {
+            return Ok(Reference::Borrowed(first));
+        }
+
+        // the data is split across chunks
+        scratch.clear();
+        scratch.extend_from_slice(first);
+        while scratch.len() < len {
+            let more = self.take(len - scratch.len())?;
+            scratch.extend_from_slice(more);
+        }
+
+        Ok(Reference::Copied(scratch))
+    }
+
+    fn peek(&mut self) -> Result<u8, Error> {
+        let mut current = self.current();
+        if current.is_empty() {
+            current = self.fetch(1)?;
+        }
+
+        Ok(current[0])
+    }
+
+    fn skip(&mut self, mut len: usize) -> Result<(), Error> {
+        while len > 0 {
+            len -= self.take(len)?.len();
+        }
+
+        Ok(())
+    }
+
+    fn begin_capture(&mut self) {
+        self.capture_starts.push((self.chunk, self.offset));
+    }
+
+    fn end_capture<'a>(&mut self, scratch: &'a mut Vec<u8>) -> Reference<'de, 'a> {
+        let position = (self.chunk, self.offset);
+        let (mut chunk, mut offset) = self.capture_starts.pop().unwrap_or(position).min(position);
+
+        if self.chunks.is_empty() {
+            return Reference::Borrowed(&[]);
+        }
+
+        // a capture that starts at the end of a chunk really starts in the next one
+        while chunk < self.chunk && offset == self.chunks[chunk].len() {
+            chunk += 1;
+            offset = 0;
+        }
+
+        // the chunks are kept while capturing, so the data can be taken straight from them
+        let first: &'de [u8] = self.chunks[chunk];
+        if chunk == self.chunk {
+            return Reference::Borrowed(&first[offset..self.offset]);
+        }
+
+        scratch.clear();
+        scratch.extend_from_slice(&first[offset..]);
+        for middle in &self.chunks[chunk + 1..self.chunk] {
+            scratch.extend_from_slice(middle);
+        }
+        scratch.extend_from_slice(&self.chunks[self.chunk][..self.offset]);
+
+        Reference::Copied(scratch)
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.checkpoints.push((self.chunk, self.offset));
+        self.checkpoints.len() - 1
+    }
+
+    fn rewind(&mut self, depth: usize) -> Result<(), Error> {
+        let (chunk, offset) = match self.checkpoints.get(depth) {
+            Some(&checkpoint) => checkpoint,
+            None => return Err(Error::BadCheckpoint),
+        };
+
+        // the chunks are kept while a checkpoint is active, so they can simply be read again
+        for start in self.capture_starts.iter_mut() {
+            *start = (*start).min((chunk, offset));
+        }
+
+        self.chunk = chunk;
+        self.offset = offset;
+        self.checkpoints.truncate(depth);
+        Ok(())
+    }
+
+    fn release(&mut self, depth: usize) {
+        self.checkpoints.truncate(depth);
     }
 }
 
@@ -73,10 +510,90 @@ impl<'de, F: FnMut(&mut [u8]) -> Result<(), Error>> Read<'de> for CopyRead<F> {
                  len: usize,
                  scratch: &'a mut Vec<u8>)
                  -> Result<Reference<'de, 'a>, Error> {
+        scratch.clear();
+        let replayed = self.replay.take(len, scratch);
         scratch.resize(len, 0);
-        (self.thunk)(scratch)?;
+
+        let start = match self.peeked {
+            Some(peeked) if replayed < len => {
+                scratch[replayed] = peeked;
+                self.peeked = None;
+                replayed + 1
+            }
+            _ => replayed,
+        };
+
+        if start < len {
+            (self.thunk)(&mut scratch[start..])?;
+        }
+
+        // only the bytes that weren't replayed are new to the recording
+        self.replay.record(&scratch[replayed..]);
+        self.capture.record(scratch);
         Ok(Reference::Copied(scratch))
     }
+
+    fn peek(&mut self) -> Result<u8, Error> {
+        if let Some(next) = self.replay.peek() {
+            return Ok(next);
+        }
+
+        if let Some(peeked) = self.peeked {
+            return Ok(peeked);
+        }
+
+        let mut buf = [0];
+        (self.thunk)(&mut buf)?;
+        self.peeked = Some(buf[0]);
+        Ok(buf[0])
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Error> {
+        let mut replayed = vec![];
+        let mut len = len - self.replay.take(len, &mut replayed);
+        self.capture.record(&replayed);
+
+        if len > 0 {
+            if let Some(peeked) = self.peeked.take() {
+                self.capture.record(&[peeked]);
+                self.replay.record(&[peeked]);
+                len -= 1;
+            }
+        }
+
+        let mut buf = [0; SKIP_CHUNK];
+        while len > 0 {
+            let chunk = if len < SKIP_CHUNK { len } else { SKIP_CHUNK };
+            (self.thunk)(&mut buf[..chunk])?;
+            self.capture.record(&buf[..chunk]);
+            self.replay.record(&buf[..chunk]);
+            len -= chunk;
+        }
+
+        Ok(())
+    }
+
+    fn begin_capture(&mut self) {
+        self.capture.begin();
+    }
+
+    fn end_capture<'a>(&mut self, scratch: &'a mut Vec<u8>) -> Reference<'de, 'a> {
+        self.capture.end(scratch)
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.replay.checkpoint()
+    }
+
+    fn rewind(&mut self, depth: usize) -> Result<(), Error> {
+        let distance = self.replay.rewind(depth)?;
+        self.capture.unrecord(distance);
+        Ok(())
+    }
+
+    fn release(&mut self, depth: usize) {
+        self.replay.release(depth);
+    }
 }
 
 mod private {
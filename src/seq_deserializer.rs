@@ -3,6 +3,15 @@
 // This Source Code Form is subject to the terms of the Mozilla Public License,
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
 // obtain one at https://mozilla.org/MPL/2.0/.
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeSet;
+
+#[cfg(not(feature = "alloc"))]
+use std::collections::BTreeSet;
+
 use serde::de::{SeqAccess, MapAccess, DeserializeSeed};
 
 use de::Deserializer;
@@ -13,6 +22,8 @@ use read::Read;
 pub struct SeqDeserializer<'de: 'a, 'a, R: 'a + Read<'de>> {
     de: &'a mut Deserializer<'de, R>,
     count: usize,
+    // encoded keys seen so far, when rejecting duplicate keys
+    keys: BTreeSet<Vec<u8>>,
 }
 
 impl<'de, 'a, R: Read<'de>> SeqDeserializer<'de, 'a, R> {
@@ -20,6 +31,16 @@ impl<'de, 'a, R: Read<'de>> SeqDeserializer<'de, 'a, R> {
         SeqDeserializer {
             de: de,
             count: count,
+            keys: BTreeSet::new(),
+        }
+    }
+
+    /// Check that the visitor read every item.
+    pub fn end(self) -> Result<(), Error> {
+        if self.count == 0 {
+            Ok(())
+        } else {
+            Err(Error::BadLength)
         }
     }
 
@@ -56,7 +77,18 @@ impl<'de, 'a, R: Read<'de>> MapAccess<'de> for SeqDeserializer<'de, 'a, R> {
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
         where K: DeserializeSeed<'de>
     {
-        self.visit_item(seed)
+        if !self.de.rejects_duplicate_keys() || self.count == 0 {
+            return self.visit_item(seed);
+        }
+
+        self.count -= 1;
+
+        let (key, encoded) = self.de.capture(|de| seed.deserialize(de))?;
+        if !self.keys.insert(encoded.to_vec()) {
+            return Err(Error::DuplicateKey);
+        }
+
+        Ok(Some(key))
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
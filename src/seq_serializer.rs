@@ -11,7 +11,7 @@ use serde::ser::{Serialize, SerializeSeq, SerializeTupleVariant, SerializeTuple,
 
 use byteorder::{ByteOrder, BigEndian};
 
-use ser::Serializer;
+use ser::{Serializer, SerializerConfig};
 
 use error::Error;
 
@@ -22,15 +22,17 @@ pub struct SeqSerializer<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> {
     size: Option<usize>,
     buffer: Vec<u8>,
     output: &'a mut F,
+    config: SerializerConfig,
 }
 
 impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> SeqSerializer<'a, F> {
-    pub fn new(output: &'a mut F) -> SeqSerializer<'a, F> {
+    pub fn new(output: &'a mut F, config: SerializerConfig) -> SeqSerializer<'a, F> {
         SeqSerializer {
             count: 0,
             size: None,
             buffer: vec![],
-            output: output,
+            output,
+            config,
         }
     }
 
@@ -83,10 +85,12 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> SeqSerializer<'a, F> {
     fn serialize_into_buffer<T>(&mut self, value: &T) -> Result<(), Error>
         where T: ?Sized + Serialize
     {
-        let mut target = Serializer::new(|bytes| {
-            self.buffer.extend_from_slice(bytes);
-            Ok(())
-        });
+        let buffer = &mut self.buffer;
+        let mut target = Serializer::with_config(|bytes| {
+                                                     buffer.extend_from_slice(bytes);
+                                                     Ok(())
+                                                 },
+                                                 self.config);
 
         value.serialize(&mut target)
     }
@@ -94,7 +98,8 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> SeqSerializer<'a, F> {
     fn serialize_directly<T>(&mut self, value: &T) -> Result<(), Error>
         where T: ?Sized + Serialize
     {
-        let mut target = Serializer::new(|bytes| (self.output)(bytes));
+        let output = &mut self.output;
+        let mut target = Serializer::with_config(|bytes| (output)(bytes), self.config);
 
         value.serialize(&mut target)
     }
@@ -3,6 +3,7 @@
 // This Source Code Form is subject to the terms of the Mozilla Public License,
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
 // obtain one at https://mozilla.org/MPL/2.0/.
+use std::convert::TryFrom;
 use std::result;
 
 use byteorder::{ByteOrder, BigEndian, LittleEndian};
@@ -20,16 +21,126 @@ use map_serializer::*;
 /// The corepack Serializer. Contains a closure that receives byte buffers as the output is created.
 pub struct Serializer<F: FnMut(&[u8]) -> Result<(), Error>> {
     output: F,
+    config: SerializerConfig,
+    raw: bool,
+}
+
+/// Options that change how the Serializer encodes values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SerializerConfig {
+    canonical: bool,
+    compact_floats: bool,
+    integral_floats: bool,
+    legacy: bool,
+    exact_integers: bool,
+    human_readable: bool,
+    bare_options: bool,
+    named_variants: bool,
+}
+
+impl SerializerConfig {
+    /// The default options.
+    pub fn new() -> SerializerConfig {
+        SerializerConfig::default()
+    }
+
+    /// Produce identical bytes for identical values.
+    ///
+    /// Map entries are sorted by their encoded keys, and maps with duplicate keys are rejected.
+    /// Non-negative integers always use the unsigned markers, and every NaN is written as the
+    /// same quiet NaN. Raw values are written as-is, so they must already be canonical.
+    pub fn canonical(mut self, canonical: bool) -> SerializerConfig {
+        self.canonical = canonical;
+        self
+    }
+
+    pub(crate) fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Write an f64 as FLOAT32 when that loses no precision.
+    pub fn compact_floats(mut self, compact_floats: bool) -> SerializerConfig {
+        self.compact_floats = compact_floats;
+        self
+    }
+
+    /// Write floats with no fractional part, like 1.0, as integers.
+    ///
+    /// Negative zero and values outside the 64-bit integer range stay floats. The Deserializer
+    /// accepts integers for float fields, so these values read back unchanged.
+    pub fn integral_floats(mut self, integral_floats: bool) -> SerializerConfig {
+        self.integral_floats = integral_floats;
+        self
+    }
+
+    /// Only use markers from the original messagepack spec, for older decoders.
+    ///
+    /// Strings never use STR8, and byte buffers are written with the raw (string) markers
+    /// instead of BIN. Raw values are written as-is, so they must already follow the old spec.
+    pub fn legacy(mut self, legacy: bool) -> SerializerConfig {
+        self.legacy = legacy;
+        self
+    }
+
+    /// Write each integer with the marker that matches its type, whatever its value.
+    ///
+    /// A u32 is always written as UINT32 and an i16 as INT16, so an integer field keeps the same
+    /// width when its value changes. 128-bit integers always use their 16 byte BIN form. This has
+    /// no effect in canonical mode, which needs the smallest marker.
+    pub fn exact_integers(mut self, exact_integers: bool) -> SerializerConfig {
+        self.exact_integers = exact_integers;
+        self
+    }
+
+    /// Report the format as human readable to the types being serialized.
+    ///
+    /// Messagepack is a binary format, so by default types like `IpAddr` use their compact form.
+    /// Turn this on to get their string form instead, as older versions of corepack did.
+    pub fn human_readable(mut self, human_readable: bool) -> SerializerConfig {
+        self.human_readable = human_readable;
+        self
+    }
+
+    /// Write None as nil and Some as the value itself, instead of `[false]` and `[true, value]`.
+    ///
+    /// This is what serde's tagged, untagged and flattened types need, since they buffer values
+    /// without knowing their types. `Some(None)` can't be told apart from `None` in this form.
+    /// The Deserializer needs `DeserializerConfig::bare_options` to read it back.
+    pub fn bare_options(mut self, bare_options: bool) -> SerializerConfig {
+        self.bare_options = bare_options;
+        self
+    }
+
+    /// Write enums as `{name: value}`, and unit variants as just their name.
+    ///
+    /// Like `bare_options`, this lets enums pass through serde's tagged, untagged and flattened
+    /// types. The Deserializer reads this form with any options.
+    pub fn named_variants(mut self, named_variants: bool) -> SerializerConfig {
+        self.named_variants = named_variants;
+        self
+    }
 }
 
 impl<F: FnMut(&[u8]) -> Result<(), Error>> Serializer<F> {
     /// Create a new Deserializer given an input function.
     pub fn new(output: F) -> Serializer<F> {
-        Serializer { output: output }
+        Serializer::with_config(output, SerializerConfig::default())
+    }
+
+    /// Create a new Serializer that uses the given options.
+    pub fn with_config(output: F, config: SerializerConfig) -> Serializer<F> {
+        Serializer {
+            output,
+            config,
+            raw: false,
+        }
     }
 
     fn serialize_signed(&mut self, value: i64) -> Result<(), Error> {
-        if value >= FIXINT_MIN as i64 && value <= FIXINT_MAX as i64 {
+        if self.config.canonical && value >= 0 {
+            // positive values are the same no matter which type they came from
+            self.serialize_unsigned(value as u64)
+        } else if value >= FIXINT_MIN as i64 && value <= FIXINT_MAX as i64 {
             let mut buf = [0; U16_BYTES];
             LittleEndian::write_i16(&mut buf, value as i16);
             (self.output)(&buf[..1])
@@ -54,7 +165,7 @@ impl<F: FnMut(&[u8]) -> Result<(), Error>> Serializer<F> {
             BigEndian::write_i32(&mut buf[1..], value as i32);
             (self.output)(&buf)
         } else if value >= 0 && value <= u32::max_value() as i64 {
-            let mut buf = [UINT32; U16_BYTES + 1];
+            let mut buf = [UINT32; U32_BYTES + 1];
             BigEndian::write_u32(&mut buf[1..], value as u32);
             (self.output)(&buf)
         } else {
@@ -84,6 +195,20 @@ impl<F: FnMut(&[u8]) -> Result<(), Error>> Serializer<F> {
         }
     }
 
+    // writes the low width bytes of value after marker
+    fn serialize_exact(&mut self, marker: u8, value: u64, width: usize) -> Result<(), Error> {
+        let mut buf = [marker; U64_BYTES + 1];
+        BigEndian::write_u64(&mut buf[1..], value);
+
+        let start = U64_BYTES - width;
+        buf[start] = marker;
+        (self.output)(&buf[start..])
+    }
+
+    fn is_exact(&self) -> bool {
+        self.config.exact_integers && !self.config.canonical
+    }
+
     fn serialize_bool(&mut self, value: bool) -> Result<(), Error> {
         if value {
             (self.output)(&[TRUE])
@@ -92,20 +217,64 @@ impl<F: FnMut(&[u8]) -> Result<(), Error>> Serializer<F> {
         }
     }
 
+    // writes a float with no fractional part as an integer, returning false if it has one
+    fn serialize_integral(&mut self, value: f64) -> Result<bool, Error> {
+        if value == 0.0 && value.is_sign_negative() {
+            // an integer can't carry the sign of negative zero
+            Ok(false)
+        } else if (-9_223_372_036_854_775_808.0..0.0).contains(&value) &&
+                  value as i64 as f64 == value {
+            self.serialize_signed(value as i64).map(|_| true)
+        } else if (0.0..18_446_744_073_709_551_616.0).contains(&value) &&
+                  value as u64 as f64 == value {
+            self.serialize_unsigned(value as u64).map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+
     fn serialize_f32(&mut self, value: f32) -> Result<(), Error> {
+        if self.config.integral_floats && self.serialize_integral(value as f64)? {
+            return Ok(());
+        }
+
         let mut buf = [FLOAT32; U32_BYTES + 1];
-        BigEndian::write_f32(&mut buf[1..], value);
+        if self.config.canonical && value.is_nan() {
+            BigEndian::write_u32(&mut buf[1..], CANONICAL_NAN32);
+        } else {
+            BigEndian::write_f32(&mut buf[1..], value);
+        }
         (self.output)(&buf)
     }
 
     fn serialize_f64(&mut self, value: f64) -> Result<(), Error> {
+        if self.config.integral_floats && self.serialize_integral(value)? {
+            return Ok(());
+        }
+
+        if self.config.compact_floats && value as f32 as f64 == value {
+            return self.serialize_f32(value as f32);
+        }
+
         let mut buf = [FLOAT64; U64_BYTES + 1];
-        BigEndian::write_f64(&mut buf[1..], value);
+        if self.config.canonical && value.is_nan() {
+            BigEndian::write_u64(&mut buf[1..], CANONICAL_NAN64);
+        } else {
+            BigEndian::write_f64(&mut buf[1..], value);
+        }
         (self.output)(&buf)
     }
 
     fn serialize_bytes(&mut self, value: &[u8]) -> Result<(), Error> {
-        if value.len() <= MAX_BIN8 {
+        if self.raw {
+            // pre-encoded data from a Raw value, splice it in as-is
+            return (self.output)(value);
+        }
+
+        if self.config.legacy {
+            // the old spec has no BIN, byte buffers were written as raw strings
+            try!(self.serialize_str_header(value.len()));
+        } else if value.len() <= MAX_BIN8 {
             try!((self.output)(&[BIN8, value.len() as u8]));
         } else if value.len() <= MAX_BIN16 {
             let mut buf = [BIN16; U16_BYTES + 1];
@@ -122,22 +291,26 @@ impl<F: FnMut(&[u8]) -> Result<(), Error>> Serializer<F> {
         (self.output)(value)
     }
 
-    fn serialize_str(&mut self, value: &str) -> Result<(), Error> {
-        if value.len() <= MAX_FIXSTR {
-            try!((self.output)(&[value.len() as u8 | FIXSTR_MASK]));
-        } else if value.len() <= MAX_STR8 {
-            try!((self.output)(&[STR8, value.len() as u8]));
-        } else if value.len() <= MAX_STR16 {
+    fn serialize_str_header(&mut self, len: usize) -> Result<(), Error> {
+        if len <= MAX_FIXSTR {
+            (self.output)(&[len as u8 | FIXSTR_MASK])
+        } else if len <= MAX_STR8 && !self.config.legacy {
+            (self.output)(&[STR8, len as u8])
+        } else if len <= MAX_STR16 {
             let mut buf = [STR16; U16_BYTES + 1];
-            BigEndian::write_u16(&mut buf[1..], value.len() as u16);
-            try!((self.output)(&buf));
-        } else if value.len() <= MAX_STR32 {
+            BigEndian::write_u16(&mut buf[1..], len as u16);
+            (self.output)(&buf)
+        } else if len <= MAX_STR32 {
             let mut buf = [STR32; U32_BYTES + 1];
-            BigEndian::write_u32(&mut buf[1..], value.len() as u32);
-            try!((self.output)(&buf));
+            BigEndian::write_u32(&mut buf[1..], len as u32);
+            (self.output)(&buf)
         } else {
-            return Err(Error::TooBig);
+            Err(Error::TooBig)
         }
+    }
+
+    fn serialize_str(&mut self, value: &str) -> Result<(), Error> {
+        try!(self.serialize_str_header(value.len()));
 
         (self.output)(value.as_bytes())
     }
@@ -146,11 +319,17 @@ impl<F: FnMut(&[u8]) -> Result<(), Error>> Serializer<F> {
         (self.output)(&[NIL])
     }
 
-    fn serialize_variant(&mut self, variant_index: u32) -> Result<(), Error> {
+    fn serialize_variant(&mut self, variant_index: u32, variant: &str) -> Result<(), Error> {
         // Serialize variants as two-tuples with the variant index and its contents.
         // Because messagepack is purely right-associative, we don't have to track
         // the variant once we get it going.
 
+        if self.config.named_variants {
+            // or as a map with a single entry
+            (self.output)(&[1u8 | FIXMAP_MASK])?;
+            return self.serialize_str(variant);
+        }
+
         // start a two element array
         (self.output)(&[2u8 | FIXARRAY_MASK])?;
 
@@ -173,7 +352,7 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     type SerializeStructVariant = Self::SerializeMap;
 
     fn serialize_seq(self, size: Option<usize>) -> result::Result<Self::SerializeSeq, Self::Error> {
-        let mut seq = SeqSerializer::new(&mut self.output);
+        let mut seq = SeqSerializer::new(&mut self.output, self.config);
 
         seq.hint_size(size)?;
 
@@ -181,7 +360,7 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     }
 
     fn serialize_map(self, size: Option<usize>) -> result::Result<Self::SerializeMap, Self::Error> {
-        let mut map = MapSerializer::new(&mut self.output);
+        let mut map = MapSerializer::new(&mut self.output, self.config);
 
         map.hint_size(size)?;
 
@@ -193,13 +372,45 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     }
 
     fn serialize_i64(self, value: i64) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(INT64, value as u64, U64_BYTES);
+        }
+
         Serializer::serialize_signed(self, value)
     }
 
     fn serialize_u64(self, value: u64) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(UINT64, value, U64_BYTES);
+        }
+
         Serializer::serialize_unsigned(self, value)
     }
 
+    // 128-bit integers use the normal markers when they fit in 64 bits, and are otherwise
+    // written as a 16 byte BIN, as described in the crate docs
+    fn serialize_i128(self, value: i128) -> Result<(), Error> {
+        if !self.is_exact() {
+            if let Ok(value) = i64::try_from(value) {
+                return Serializer::serialize_signed(self, value);
+            } else if let Ok(value) = u64::try_from(value) {
+                return Serializer::serialize_unsigned(self, value);
+            }
+        }
+
+        Serializer::serialize_bytes(self, &value.to_be_bytes())
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<(), Error> {
+        if !self.is_exact() {
+            if let Ok(value) = u64::try_from(value) {
+                return Serializer::serialize_unsigned(self, value);
+            }
+        }
+
+        Serializer::serialize_bytes(self, &value.to_be_bytes())
+    }
+
     fn serialize_f32(self, value: f32) -> Result<(), Error> {
         Serializer::serialize_f32(self, value)
     }
@@ -221,26 +432,50 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     }
 
     fn serialize_i8(self, value: i8) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(INT8, value as i64 as u64, 1);
+        }
+
         Serializer::serialize_signed(self, value as i64)
     }
 
     fn serialize_i16(self, value: i16) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(INT16, value as i64 as u64, U16_BYTES);
+        }
+
         Serializer::serialize_signed(self, value as i64)
     }
 
     fn serialize_i32(self, value: i32) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(INT32, value as i64 as u64, U32_BYTES);
+        }
+
         Serializer::serialize_signed(self, value as i64)
     }
 
     fn serialize_u8(self, value: u8) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(UINT8, value as u64, 1);
+        }
+
         Serializer::serialize_unsigned(self, value as u64)
     }
 
     fn serialize_u16(self, value: u16) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(UINT16, value as u64, U16_BYTES);
+        }
+
         Serializer::serialize_unsigned(self, value as u64)
     }
 
     fn serialize_u32(self, value: u32) -> Result<(), Error> {
+        if self.is_exact() {
+            return self.serialize_exact(UINT32, value as u64, U32_BYTES);
+        }
+
         Serializer::serialize_unsigned(self, value as u64)
     }
 
@@ -256,15 +491,26 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     fn serialize_unit_variant(self,
                               _: &'static str,
                               index: u32,
-                              _: &'static str)
+                              variant: &'static str)
                               -> Result<(), Error> {
-        self.serialize_variant(index)?;
+        if self.config.named_variants {
+            return self.serialize_str(variant);
+        }
+
+        self.serialize_variant(index, variant)?;
         self.serialize_unit()
     }
 
-    fn serialize_newtype_struct<T>(self, _: &'static str, value: &T) -> Result<(), Error>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<(), Error>
         where T: ?Sized + serde::Serialize
     {
+        if name == RAW_TOKEN {
+            self.raw = true;
+            let result = value.serialize(&mut *self);
+            self.raw = false;
+            return result;
+        }
+
         // serialize newtypes directly
         value.serialize(self)
     }
@@ -272,22 +518,30 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     fn serialize_newtype_variant<T>(self,
                                     name: &'static str,
                                     variant_index: u32,
-                                    _: &'static str,
+                                    variant: &'static str,
                                     value: &T)
                                     -> Result<(), Error>
         where T: ?Sized + serde::Serialize
     {
-        self.serialize_variant(variant_index)?;
+        self.serialize_variant(variant_index, variant)?;
         self.serialize_newtype_struct(name, value)
     }
 
     fn serialize_none(self) -> Result<(), Error> {
+        if self.config.bare_options {
+            return self.serialize_unit();
+        }
+
         (false,).serialize(self)
     }
 
     fn serialize_some<V>(self, value: &V) -> Result<(), Self::Error>
         where V: ?Sized + serde::Serialize
     {
+        if self.config.bare_options {
+            return value.serialize(self);
+        }
+
         (true, value).serialize(self)
     }
 
@@ -305,10 +559,10 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     fn serialize_tuple_variant(self,
                                name: &'static str,
                                index: u32,
-                               _: &'static str,
+                               variant: &'static str,
                                len: usize)
                                -> result::Result<Self::SerializeTupleVariant, Self::Error> {
-        self.serialize_variant(index)?;
+        self.serialize_variant(index, variant)?;
         self.serialize_tuple_struct(name, len)
     }
 
@@ -322,12 +576,16 @@ impl<'a, F: 'a + FnMut(&[u8]) -> Result<(), Error>> serde::Serializer for &'a mu
     fn serialize_struct_variant(self,
                                 name: &'static str,
                                 index: u32,
-                                _: &'static str,
+                                variant: &'static str,
                                 len: usize)
                                 -> result::Result<Self::SerializeStructVariant, Self::Error> {
-        self.serialize_variant(index)?;
+        self.serialize_variant(index, variant)?;
         self.serialize_struct(name, len)
     }
+
+    fn is_human_readable(&self) -> bool {
+        self.config.human_readable
+    }
 }
 
 #[cfg(test)]
@@ -395,4 +653,116 @@ mod test {
                    &[0x83, 0xa3, 0x6f, 0x6e, 0x65, 0x01, 0xa5, 0x74, 0x68, 0x72, 0x65, 0x65,
                      0x03, 0xa3, 0x74, 0x77, 0x6f, 0x02]);
     }
+
+    #[test]
+    fn compact_floats_test() {
+        let config = ::SerializerConfig::new().compact_floats(true);
+
+        assert_eq!(::to_bytes_with_config(0.5f64, config).unwrap(),
+                   &[0xca, 0x3f, 0x00, 0x00, 0x00]);
+        assert_eq!(::to_bytes_with_config(0.1f64, config).unwrap(),
+                   &[0xcb, 0x3f, 0xb9, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]);
+    }
+
+    #[test]
+    fn integral_floats_test() {
+        let config = ::SerializerConfig::new().integral_floats(true);
+
+        assert_eq!(::to_bytes_with_config(1.0f64, config).unwrap(), &[0x01]);
+        assert_eq!(::to_bytes_with_config(-300.0f32, config).unwrap(), &[0xd1, 0xfe, 0xd4]);
+        assert_eq!(::to_bytes_with_config(-0.0f64, config).unwrap(),
+                   &[0xcb, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(::to_bytes_with_config(1e20f64, config).unwrap(),
+                   &[0xcb, 0x44, 0x15, 0xaf, 0x1d, 0x78, 0xb5, 0x8c, 0x40]);
+
+        let values = vec![1.0, 0.5, -2.0, 1e20];
+        let bytes = ::to_bytes_with_config(&values, config.compact_floats(true)).unwrap();
+        assert_eq!(::from_bytes::<Vec<f64>>(&bytes).unwrap(), values);
+    }
+
+    #[test]
+    fn legacy_test() {
+        let config = ::SerializerConfig::new().legacy(true);
+        let long = "x".repeat(32);
+
+        // STR16 instead of STR8
+        let bytes = ::to_bytes_with_config(&long, config).unwrap();
+        assert_eq!(&bytes[..3], &[0xda, 0x00, 0x20]);
+        assert_eq!(bytes.len(), 35);
+
+        // byte buffers use the raw (string) markers
+        assert_eq!(::to_bytes_with_config(::Raw::new(vec![0xc0]).unwrap(), config).unwrap(),
+                   &[0xc0]);
+        assert_eq!(::to_bytes_with_config(Bytes(b"hi"), config).unwrap(),
+                   &[0xa2, 0x68, 0x69]);
+    }
+
+    struct Bytes(&'static [u8]);
+
+    impl ::serde::Serialize for Bytes {
+        fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Record {
+        a: u8,
+        b: i16,
+        c: u32,
+        d: i64,
+    }
+
+    #[test]
+    fn exact_integers_test() {
+        let config = ::SerializerConfig::new().exact_integers(true);
+        let record = Record {
+            a: 1,
+            b: -1,
+            c: 2,
+            d: -3,
+        };
+
+        assert_eq!(::to_bytes_with_config(&[record], config).unwrap(),
+                   &[0x91,
+                     0x84,
+                     0xa1, 0x61, 0xcc, 0x01,
+                     0xa1, 0x62, 0xd1, 0xff, 0xff,
+                     0xa1, 0x63, 0xce, 0x00, 0x00, 0x00, 0x02,
+                     0xa1, 0x64, 0xd3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfd]);
+
+        // the width doesn't depend on the value
+        let small = ::to_bytes_with_config(Record { a: 0, b: 0, c: 0, d: 0 }, config).unwrap();
+        let large = ::to_bytes_with_config(Record {
+                                               a: 255,
+                                               b: i16::MIN,
+                                               c: u32::MAX,
+                                               d: i64::MAX,
+                                           },
+                                           config)
+            .unwrap();
+        assert_eq!(small.len(), large.len());
+    }
+
+    #[test]
+    fn uint32_from_signed_test() {
+        assert_eq!(::to_bytes(3_000_000_000i64).unwrap(), &[0xce, 0xb2, 0xd0, 0x5e, 0x00]);
+    }
+
+    #[test]
+    fn int128_test() {
+        assert_eq!(::to_bytes(5u128).unwrap(), &[0x05]);
+        assert_eq!(::to_bytes(-300i128).unwrap(), &[0xd1, 0xfe, 0xd4]);
+        assert_eq!(::to_bytes(u64::MAX as i128).unwrap(),
+                   &[0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(::to_bytes(1u128 << 64).unwrap(),
+                   &[0xc4, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(::to_bytes(i128::MIN).unwrap(),
+                   &[0xc4, 0x10, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let config = ::SerializerConfig::new().exact_integers(true);
+        assert_eq!(::to_bytes_with_config(1u128, config).unwrap().len(), 18);
+    }
 }
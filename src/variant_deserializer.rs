@@ -3,11 +3,8 @@
 // This Source Code Form is subject to the terms of the Mozilla Public License,
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
 // obtain one at https://mozilla.org/MPL/2.0/.
-#[cfg(feature = "alloc")]
-use alloc::borrow::ToOwned;
-
-use serde::de::{IntoDeserializer, DeserializeSeed, EnumAccess, Visitor, Deserialize, VariantAccess};
-use serde::de::value::StringDeserializer;
+use serde::de::{DeserializeSeed, EnumAccess, Visitor, VariantAccess, Unexpected};
+use serde::de::Error as DeError;
 
 use de::Deserializer;
 
@@ -17,6 +14,8 @@ use read::Read;
 pub struct VariantDeserializer<'de: 'a, 'a, R: 'a + Read<'de>> {
     de: &'a mut Deserializer<'de, R>,
     variants: &'static [&'static str],
+    // false for unit variants written without a value
+    payload: bool,
 }
 
 impl<'de, 'a, R: Read<'de>> VariantDeserializer<'de, 'a, R> {
@@ -26,6 +25,15 @@ impl<'de, 'a, R: Read<'de>> VariantDeserializer<'de, 'a, R> {
         VariantDeserializer {
             de: de,
             variants: variants,
+            payload: true,
+        }
+    }
+
+    fn check_payload(&self, expected: &'static str) -> Result<(), Error> {
+        if self.payload {
+            Ok(())
+        } else {
+            Err(Error::invalid_type(Unexpected::UnitVariant, &expected))
         }
     }
 }
@@ -34,21 +42,11 @@ impl<'de, 'a, R: Read<'de>> EnumAccess<'de> for VariantDeserializer<'de, 'a, R>
     type Error = Error;
     type Variant = VariantDeserializer<'de, 'a, R>;
 
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Error>
+    fn variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self::Variant), Error>
         where V: DeserializeSeed<'de>
     {
-        // get the variant index with a one-item tuple
-        let variant_index_container: (usize, /* enum-type */) =
-            Deserialize::deserialize(&mut *self.de)?;
-
-        // the other value in this tuple would be the actual value of the enum,
-        // but we don't know what that is
-        let (variant_index /* enum-value */,) = variant_index_container;
-
-        // translate that to the name of the variant
-        let name = self.variants[variant_index].to_owned();
-        let de: StringDeserializer<Error> = name.into_deserializer();
-        let value = seed.deserialize(de)?;
+        let (value, payload) = self.de.parse_variant(seed, self.variants)?;
+        self.payload = payload;
 
         Ok((value, self))
     }
@@ -60,22 +58,31 @@ impl<'de, 'a, R: Read<'de>> VariantAccess<'de> for VariantDeserializer<'de, 'a,
     fn tuple_variant<V>(self, _: usize, visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
+        self.check_payload("tuple variant")?;
         ::serde::Deserializer::deserialize_any(self.de, visitor)
     }
 
     fn struct_variant<V>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error>
         where V: Visitor<'de>
     {
+        self.check_payload("struct variant")?;
         ::serde::Deserializer::deserialize_any(self.de, visitor)
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
         where T: DeserializeSeed<'de>
     {
+        self.check_payload("newtype variant")?;
         seed.deserialize(self.de)
     }
 
     fn unit_variant(self) -> Result<(), Error> {
-        Deserialize::deserialize(&mut *self.de)
+        // unit variants are written with nil, but an unknown variant caught by #[serde(other)]
+        // may carry anything
+        if self.payload {
+            self.de.skip_value()
+        } else {
+            Ok(())
+        }
     }
 }